@@ -17,7 +17,7 @@ impl Ticker {
         tokio::spawn({
             let cancellation = cancellation.clone();
             async move {
-                Self::timer_thread(duration, cancellation, f)
+                Self::timer_thread(duration, cancellation, f).await
             }
         });
         Self { cancellation }
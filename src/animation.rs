@@ -0,0 +1,381 @@
+//! Animated GIF and PNG files, for palette cycling and short zooms.
+//!
+//! GIF images have at most 256 colors, so frames are matched to
+//! 256 colors sampled from the gradient, see `cycle_colors`.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{ self, BufWriter, Write },
+    path::{ Path, PathBuf },
+};
+use crate::colorizer::{ self, Colorizer };
+use crate::gradient::Gradient;
+use crate::mandelbrot::MandelbrotSetWithHistogram;
+use crate::png::ApngWriter;
+
+/// `n` colors going through `gradient` and back, so that they can
+/// be cycled without a jump.
+pub fn cycle_colors(gradient: &Gradient, n: usize) -> Vec<(u8, u8, u8)> {
+    (0..n)
+        .map(|i| gradient.sample_mirrored(2.0 * i as f32 / n as f32))
+        .collect()
+}
+
+/// The colors of `frame` in a palette cycling animation of `frames`,
+/// rotating `colors` once over the whole animation.
+pub fn cycle(colors: &[(u8, u8, u8)], frame: usize, frames: usize) -> Vec<(u8, u8, u8)> {
+    let offset = frame * colors.len() / frames.max(1);
+    colors
+        .iter()
+        .cycle()
+        .skip(offset)
+        .take(colors.len())
+        .copied()
+        .collect()
+}
+
+/// Writes an animation of `frames` cycling the colors of `set`,
+/// colored by the colorizer `colorizer` makes for each cycled
+/// gradient, see `AnimationWriter::create`.
+pub fn write_palette_cycle(
+    path: &Path,
+    set: &MandelbrotSetWithHistogram,
+    gradient: &Gradient,
+    colorizer: impl Fn(&Gradient) -> Box<dyn Colorizer>,
+    frames: usize,
+    delay_ms: u16,
+    text: &[(String, String)]
+) -> Result<(), String> {
+    let (w, h) = set.size();
+    let colors = cycle_colors(gradient, 256);
+    let mut writer = AnimationWriter::create(path, w, h, frames, delay_ms, gradient, text)?;
+
+    for frame in 0..frames {
+        let cycled = Gradient::from_colors(&cycle(&colors, frame, frames));
+        writer.write_frame(&colorizer::colorize(set, colorizer(&cycled).as_mut()))?;
+    }
+
+    writer.finish()
+}
+
+/// Writes a GIF animation, looping forever, with a single color table.
+pub struct GifWriter<W: Write> {
+    writer: W,
+    w: usize,
+    h: usize,
+    delay_ms: u16,
+    colors: Vec<(u8, u8, u8)>,
+    /// Index in `colors` of the colors met so far.
+    nearest: HashMap<(u8, u8, u8), u8>,
+}
+
+impl<W: Write> GifWriter<W> {
+    /// Frames are shown for `delay_ms` milliseconds, rounded to
+    /// hundredths of second, and their colors are matched to the
+    /// closest of `colors`, of which only the first 256 are used.
+    pub fn new(
+        mut writer: W,
+        w: usize,
+        h: usize,
+        delay_ms: u16,
+        colors: &[(u8, u8, u8)]
+    ) -> io::Result<Self> {
+        if w == 0 || h == 0 || w > u16::MAX as usize || h > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid GIF size"));
+        }
+
+        if colors.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No colors"));
+        }
+
+        let mut colors = colors[..colors.len().min(256)].to_vec();
+        colors.resize(256, (0, 0, 0));
+
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&(w as u16).to_le_bytes())?;
+        writer.write_all(&(h as u16).to_le_bytes())?;
+        // Global color table of 256 colors, 8 bits per channel,
+        // background color and aspect ratio
+        writer.write_all(&[0xf7, 0, 0])?;
+        for &(r, g, b) in &colors {
+            writer.write_all(&[r, g, b])?;
+        }
+        // Loops forever
+        writer.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+        Ok(Self { writer, w, h, delay_ms, colors, nearest: HashMap::new() })
+    }
+
+    /// Writes a frame stored bottom-up, as `MandelbrotSetWithHistogram`
+    /// returns them.
+    pub fn write_frame(&mut self, image: &[(u8, u8, u8)]) -> io::Result<()> {
+        if image.len() != self.w * self.h {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Frame doesn't fit the animation"
+            ));
+        }
+
+        let indices: Vec<u8> = image
+            .chunks(self.w)
+            .rev()
+            .flatten()
+            .map(|&color| self.index(color))
+            .collect();
+
+        // Graphic control extension: no disposal, delay, no transparency
+        self.writer.write_all(&[0x21, 0xf9, 4, 0])?;
+        self.writer.write_all(&(self.delay_ms.div_ceil(10)).to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+
+        // Image descriptor covering the whole image, no local color table
+        self.writer.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.writer.write_all(&(self.w as u16).to_le_bytes())?;
+        self.writer.write_all(&(self.h as u16).to_le_bytes())?;
+        self.writer.write_all(&[0])?;
+
+        // Minimum code size, then data in blocks of at most 255 bytes
+        self.writer.write_all(&[8])?;
+        for block in lzw(&indices).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0x3b])?;
+        Ok(self.writer)
+    }
+
+    fn index(&mut self, color: (u8, u8, u8)) -> u8 {
+        let colors = &self.colors;
+        *self.nearest.entry(color).or_insert_with(|| {
+            let distance = |&(r, g, b): &(u8, u8, u8)| {
+                let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+                d(r, color.0) + d(g, color.1) + d(b, color.2)
+            };
+            (0..colors.len())
+                .min_by_key(|&i| distance(&colors[i]))
+                .unwrap_or(0) as u8
+        })
+    }
+}
+
+/// GIF flavored LZW compression of 8 bits indices: codes grow from
+/// 9 to 12 bits, and the table is cleared when it is full.
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 256;
+    const END: u16 = 257;
+
+    let mut output = Vec::new();
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    let mut code_size = 9;
+    let mut next = END + 1;
+    let mut table = HashMap::<(u16, u8), u16>::new();
+
+    let mut emit = |code: u16, code_size: u32| {
+        bits |= (code as u32) << bit_count;
+        bit_count += code_size;
+        while bit_count >= 8 {
+            output.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    emit(CLEAR, code_size);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        emit(END, code_size);
+        if bit_count > 0 {
+            output.push(bits as u8);
+        }
+        return output;
+    };
+
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        emit(prefix, code_size);
+        if next < 4096 {
+            table.insert((prefix, index), next);
+            // Decoders add their codes one step later, the size grows
+            // once the next code doesn't fit anymore
+            if next == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+            next += 1;
+        } else {
+            emit(CLEAR, code_size);
+            table.clear();
+            next = END + 1;
+            code_size = 9;
+        }
+        prefix = index as u16;
+    }
+
+    emit(prefix, code_size);
+    emit(END, code_size);
+    if bit_count > 0 {
+        output.push(bits as u8);
+    }
+
+    output
+}
+
+/// An animation file in the format matching the extension of its
+/// path, `.gif` or `.png`.
+pub struct AnimationWriter {
+    path: PathBuf,
+    format: Format,
+}
+
+enum Format {
+    Gif(GifWriter<BufWriter<File>>),
+    Apng(ApngWriter<BufWriter<File>>),
+}
+
+impl AnimationWriter {
+    /// `gradient` gives the colors of GIF files, `text` is stored
+    /// in PNG files only.
+    pub fn create(
+        path: &Path,
+        w: usize,
+        h: usize,
+        frames: usize,
+        delay_ms: u16,
+        gradient: &Gradient,
+        text: &[(String, String)]
+    ) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if extension != "gif" && extension != "png" {
+            return Err(format!("Unsupported animation format: {}", path.to_string_lossy()));
+        }
+
+        let file = File::create(path)
+            .map_err(|e| format!("Error creating {}: {}", path.to_string_lossy(), e))?;
+        let writer = BufWriter::new(file);
+        let format = if extension == "gif" {
+            GifWriter::new(writer, w, h, delay_ms, &cycle_colors(gradient, 256)).map(Format::Gif)
+        } else {
+            ApngWriter::new(writer, w, h, frames, delay_ms, text).map(Format::Apng)
+        };
+
+        Ok(Self {
+            format: format.map_err(|e| write_error(path, e))?,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Writes a frame stored bottom-up.
+    pub fn write_frame(&mut self, image: &[(u8, u8, u8)]) -> Result<(), String> {
+        match &mut self.format {
+            Format::Gif(gif) => gif.write_frame(image),
+            Format::Apng(apng) => apng.write_frame(image),
+        }
+            .map_err(|e| write_error(&self.path, e))
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self.format {
+            Format::Gif(gif) => gif.finish(),
+            Format::Apng(apng) => apng.finish(),
+        }
+            .and_then(|mut writer| writer.flush())
+            .map_err(|e| write_error(&self.path, e))
+    }
+}
+
+fn write_error(path: &Path, e: io::Error) -> String {
+    format!("Error writing {}: {}", path.to_string_lossy(), e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes GIF LZW data with a minimum code size of 8, returning
+    /// the indices, the largest code size met and the number of clear
+    /// codes after the first one.
+    fn decode(data: &[u8]) -> (Vec<u8>, u32, usize) {
+        let (mut bits, mut bit_count, mut bytes) = (0u32, 0u32, data.iter());
+        let mut code_size = 9;
+        let mut largest = code_size;
+        let mut clears = 0usize;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut previous: Option<Vec<u8>> = None;
+        let mut output = Vec::new();
+
+        loop {
+            while bit_count < code_size {
+                bits |= (*bytes.next().expect("missing end code") as u32) << bit_count;
+                bit_count += 8;
+            }
+            let code = (bits & ((1 << code_size) - 1)) as usize;
+            bits >>= code_size;
+            bit_count -= code_size;
+
+            match code {
+                256 => {
+                    clears += 1;
+                    table = (0..=255).map(|i| vec![i]).chain([Vec::new(), Vec::new()]).collect();
+                    code_size = 9;
+                    previous = None;
+                    continue;
+                },
+                257 => break,
+                _ => {},
+            }
+
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) if code == table.len() => {
+                    [previous.as_slice(), &previous[..1]].concat()
+                },
+                _ => panic!("invalid code {}", code),
+            };
+            output.extend(&entry);
+            if let Some(previous) = previous.filter(|_| table.len() < 4096) {
+                table.push([previous.as_slice(), &entry[..1]].concat());
+            }
+            if table.len() == 1 << code_size && code_size < 12 {
+                code_size += 1;
+                largest = largest.max(code_size);
+            }
+            previous = Some(entry);
+        }
+
+        (output, largest, clears.saturating_sub(1))
+    }
+
+    #[test]
+    fn lzw_round_trip() {
+        assert_eq!(decode(&lzw(&[])), (Vec::new(), 9, 0));
+        assert_eq!(decode(&lzw(&[7; 1000])).0, vec![7; 1000]);
+
+        // Random indices fill the table, which grows to 12 bits
+        // codes and is cleared
+        let mut seed = 1u32;
+        let noise: Vec<u8> = (0..20000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 24) as u8
+            })
+            .collect();
+        let (decoded, largest, clears) = decode(&lzw(&noise));
+        assert_eq!(decoded, noise);
+        assert_eq!(largest, 12);
+        assert!(clears >= 1);
+    }
+}
@@ -0,0 +1,262 @@
+//! Renders a zoom movie through a list of keyframes.
+
+use color_eyre::eyre::{ eyre, Result };
+use mandelbrot_rs::{
+    animation::AnimationWriter,
+    colorizer::PaletteMap,
+    export,
+    gradient::Interpolation,
+    location::{ Location, PaletteSource },
+    mandelbrot::{ Coloring, ComputeOptions, Sector },
+    movie::{ self, FastZoom, Y4mWriter },
+    palette,
+};
+use std::{
+    env,
+    fs,
+    io,
+    path::PathBuf,
+};
+use tokio::runtime::Runtime;
+use tokio_util::sync::CancellationToken;
+
+type Real = f64;
+
+const USAGE: &str = "\
+Usage: mandelbrot-movie [options] -k <location> -k <location>... -o <output>
+
+Options:
+    -k, --keyframe <file>   parameter, .kfr, .xpf or .png file, at least two
+    -n, --frames <n>        number of frames, 300 by default
+    -s, --size <w>x<h>      frame size in pixels, 640x480 by default
+    -i, --maxiter <n>       maximum number of iterations, the highest
+                            of the keyframes by default
+    -p, --palette <file>    .gpl, .map, .ugr, .ggr or .json palette, that
+                            of the first keyframe by default
+        --interpolation <mode>
+                            how colors are blended between palette
+                            entries: rgb, linear-rgb, hsv, oklab or spline
+        --period <n>        iterations to go through the palette, which
+                            then goes back the other way, 64 by default
+        --fps <n>           frames per second, 30 by default
+        --resume <n>        start from frame n, to continue a render
+        --fast              zoom straight into the center of the last
+                            keyframe, rendering an image every time the
+                            radius halves and resampling frames from it
+    -o, --output <output>   directory for numbered PNG frames, an animated
+                            .gif or .png file, or - to write a YUV4MPEG2
+                            stream to stdout
+    -h, --help              show this message
+
+The zoom is exponential and the center moves at constant speed on
+screen, colors follow the smooth iteration count so that they don't
+flicker between frames. To encode the stream:
+
+    mandelbrot-movie -k a.toml -k b.toml -o - | ffmpeg -i - zoom.mp4";
+
+/// Command line options, `None` when not given.
+#[derive(Default)]
+struct Options {
+    keyframes: Vec<PathBuf>,
+    frames: Option<usize>,
+    size: Option<(usize, usize)>,
+    maxiter: Option<usize>,
+    palette: Option<PathBuf>,
+    interpolation: Option<Interpolation>,
+    period: Option<f32>,
+    fps: Option<usize>,
+    resume: Option<usize>,
+    fast: bool,
+    output: Option<PathBuf>,
+}
+
+pub fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let Some(options) = parse_args(env::args().skip(1)).map_err(|e| eyre!("{}\n\n{}", e, USAGE))? else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+
+    render(options).map_err(|e| eyre!(e))
+}
+
+/// Returns `None` when help was asked for.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg));
+
+        match arg.as_str() {
+            "-k" | "--keyframe" => options.keyframes.push(value()?.into()),
+            "-n" | "--frames" => options.frames = Some(parse_number(&value()?)?),
+            "-s" | "--size" => options.size = Some(parse_size(&value()?)?),
+            "-i" | "--maxiter" => options.maxiter = Some(parse_number(&value()?)?),
+            "-p" | "--palette" => options.palette = Some(value()?.into()),
+            "--interpolation" => options.interpolation = Some(Interpolation::from_name(&value()?)?),
+            "--period" => options.period = Some(parse_number(&value()?)?),
+            "--fps" => options.fps = Some(parse_number(&value()?)?),
+            "--resume" => options.resume = Some(parse_number(&value()?)?),
+            "--fast" => options.fast = true,
+            "-o" | "--output" => options.output = Some(value()?.into()),
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    Ok(Some(options))
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text
+        .parse()
+        .map_err(|_| format!("Invalid number \"{}\"", text))
+}
+
+fn parse_size(text: &str) -> Result<(usize, usize), String> {
+    text
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .ok_or_else(|| format!("Invalid size \"{}\", expected <width>x<height>", text))
+}
+
+/// Where frames go.
+enum Output {
+    Frames(PathBuf),
+    Animation(AnimationWriter),
+    Y4m(Y4mWriter<io::StdoutLock<'static>>),
+}
+
+fn render(options: Options) -> Result<(), String> {
+    let output = options.output.ok_or_else(|| "No output given".to_string())?;
+    if options.keyframes.len() < 2 {
+        return Err("At least two keyframes are needed".to_string());
+    }
+
+    let (w, h) = options.size.unwrap_or((640, 480));
+    let mut locations = Vec::new();
+    for path in &options.keyframes {
+        let (location, warnings) = Location::<Real>::open(path, w, h)?;
+        for warning in warnings {
+            eprintln!("warning: {}: {}", path.to_string_lossy(), warning);
+        }
+        locations.push(location);
+    }
+
+    let keyframes: Vec<Sector<Real>> = locations
+        .iter()
+        .map(|l| l.sector.with_size(w, h))
+        .collect();
+    let frames = options.frames.unwrap_or(300);
+    let maxiter = options.maxiter
+        .or(locations.iter().map(|l| l.maxiter).max())
+        .unwrap_or(20000);
+    let (mut palette, palette_name) = match &options.palette {
+        Some(path) => (
+            palette::read_palette(path)?,
+            path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+        ),
+        None => (locations[0].load_palette()?, locations[0].palette_name.clone()),
+    };
+    if let Some(interpolation) = options.interpolation {
+        palette.interpolation = interpolation;
+    }
+    let period = options.period.unwrap_or(64.0);
+    let first = options.resume.unwrap_or(0);
+    let fps = options.fps.unwrap_or(30).max(1);
+    let metadata = |sector: Sector<Real>| Location {
+        sector,
+        coordinates: None,
+        formula: locations[0].formula,
+        maxiter,
+        bailout: None,
+        stripe_density: None,
+        coloring: Coloring::Smooth,
+        layers: Vec::new(),
+        shading: None,
+        palette: PaletteSource::Embedded(palette.clone()),
+        palette_map: PaletteMap::default(),
+        palette_name: palette_name.clone(),
+    }.to_png_text();
+
+    let extension = output
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut output = if output.as_os_str() == "-" {
+        Output::Y4m(
+            Y4mWriter::new(io::stdout().lock(), w, h, fps)
+                .map_err(|e| e.to_string())?
+        )
+    } else if extension == "gif" || extension == "png" {
+        if first != 0 {
+            return Err("Animations can't be resumed".to_string());
+        }
+        Output::Animation(AnimationWriter::create(
+            &output, w, h, frames, (1000 / fps) as u16, &palette, &metadata(keyframes[0].clone())
+        )?)
+    } else {
+        fs::create_dir_all(&output)
+            .map_err(|e| format!("Error creating {}: {}", output.to_string_lossy(), e))?;
+        Output::Frames(output)
+    };
+
+    let fast = if options.fast {
+        if keyframes.len() > 2 {
+            eprintln!("warning: the fast mode only uses the first and last keyframes");
+        }
+        let fast = FastZoom::new(&keyframes[0], &keyframes[keyframes.len() - 1], frames)?;
+        eprintln!("Rendering {} keyframes for {} frames", fast.keyframes(), frames);
+        Some(fast)
+    } else {
+        None
+    };
+
+    let runtime = Runtime::new().map_err(|e| e.to_string())?;
+    let compute = |sector: Sector<Real>| runtime
+        .block_on(sector.compute(ComputeOptions::new(maxiter), CancellationToken::new()))
+        .ok_or_else(|| "Computation interrupted".to_string())
+        .map(|set| set.get_image_cyclic(&palette, period));
+    let mut keyframe_index = None;
+    let mut keyframe_image = Vec::new();
+
+    for frame in first..frames {
+        let (sector, image) = match &fast {
+            Some(fast) => {
+                let keyframe = fast.keyframe_for(frame);
+                if keyframe_index != Some(keyframe) {
+                    eprintln!("Keyframe {}/{}", keyframe + 1, fast.keyframes());
+                    keyframe_image = compute(fast.keyframe_sector(keyframe))?;
+                    keyframe_index = Some(keyframe);
+                }
+                (fast.frame_sector(frame), fast.resample(&keyframe_image, frame))
+            },
+            None => {
+                let sector = movie::frame_sector(&keyframes, frame, frames);
+                (sector.clone(), compute(sector)?)
+            },
+        };
+        let (re, im, radius) = sector.center_radius();
+        eprintln!("Frame {}/{}: {} {} radius {}", frame + 1, frames, re, im, radius);
+
+        match &mut output {
+            Output::Frames(dir) => {
+                let path = dir.join(format!("{:05}.png", frame));
+                export::write_image(&path, &image, w, h, &metadata(sector))?;
+            },
+            Output::Animation(writer) => writer.write_frame(&image)?,
+            Output::Y4m(writer) => writer
+                .write_frame(&image)
+                .map_err(|e| e.to_string())?,
+        }
+    }
+
+    match output {
+        Output::Animation(writer) => writer.finish(),
+        _ => Ok(()),
+    }
+}
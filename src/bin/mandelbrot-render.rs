@@ -0,0 +1,380 @@
+//! Renders a view to an image file without opening a window.
+
+use color_eyre::eyre::{ eyre, Result };
+use mandelbrot_rs::{
+    animation,
+    colorizer::{ self, Layer, PaletteMap, Shading },
+    dither::Dither,
+    export::{ self, ImageWriter },
+    gradient::{ Gradient, Interpolation },
+    location::{ self, Location, PaletteSource },
+    mandelbrot::{ self, Coloring, Formula, Progress, Sector },
+    palette,
+    poster,
+    raw,
+};
+use std::{
+    env,
+    fs,
+    path::PathBuf,
+    time::{ Duration, Instant },
+};
+use tokio::{
+    runtime::Runtime,
+    sync::watch,
+};
+use tokio_util::sync::CancellationToken;
+
+type Real = f64;
+
+/// Time between two progress lines on the terminal.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+/// Clears the rest of the terminal line, left over from a longer one.
+const CLEAR_LINE: &str = "\x1b[K";
+
+const USAGE: &str = "\
+Usage: mandelbrot-render [options] -o <image> [-d <data>]
+
+Options:
+    -l, --location <file>   start from a parameter, .kfr, .xpf or .png file
+        --re <number>       real part of the center
+        --im <number>       imaginary part of the center
+        --radius <number>   distance from the center to the top edge
+        --rotation <deg>    counterclockwise rotation in degrees
+    -s, --size <w>x<h>      image size in pixels, 800x600 by default
+    -i, --maxiter <n>       maximum number of iterations, 20000 by default
+        --bailout <radius>  radius beyond which points escape, 2 by default
+                            or 1024 for colorings by the final angle
+                            and averages over the orbit
+        --stripe-density <n>
+                            number of stripes of the stripe coloring,
+                            5 by default
+    -p, --palette <file>    .gpl, .map, .ugr, .ggr or .json palette, black
+                            and white by default
+        --interpolation <mode>
+                            how colors are blended between palette
+                            entries: rgb, linear-rgb, hsv, oklab or spline
+        --palette-map <map> how the histogram coloring lays the palette out,
+                            as \"<offset> <repeat> [<density>] [reverse]
+                            [proportional]\", density being linear, log
+                            or sqrt, e.g. \"0.5 3 log\"
+    -f, --formula <name>    formula to iterate, only \"mandelbrot\" for now
+        --coloring <name>   histogram, linear, log, sqrt, smooth, distance,
+                            angle, stripe, tia, binary, decomposition or
+                            field-lines, histogram by default
+        --layer <layer>     coloring drawn over the others, as
+                            \"<coloring> [<blend> [<opacity>]]\", blend
+                            being normal, multiply, screen, overlay, add,
+                            darken, lighten or difference; repeatable
+        --shade <az>,<el>   light the image as a relief from the azimuth
+                            and elevation in degrees, e.g. 135,45
+        --dither <mode>     how colors are rounded to 8 bits: none, ordered
+                            or blue-noise, none by default
+    -o, --output <file>     image to write, .png or .ppm
+    -d, --data <file>       also write iterations, smooth iterations, distance
+                            estimate and interior layers, .npy, .exr or .tiff
+    -c, --cycle <n>         write a palette cycling animation of n frames,
+                            the output being a .gif or .png file
+        --delay <ms>        time each animation frame is shown, 50 by default
+    -t, --tile <n>          compute the image in tiles of n by n pixels,
+                            for images too large to fit in memory
+    -h, --help              show this message
+
+Options given on the command line override the ones in the location file.";
+
+/// Command line options, `None` when not given.
+#[derive(Default)]
+struct Options {
+    location: Option<PathBuf>,
+    re: Option<String>,
+    im: Option<String>,
+    radius: Option<String>,
+    rotation: Option<f64>,
+    size: Option<(usize, usize)>,
+    maxiter: Option<usize>,
+    bailout: Option<f64>,
+    stripe_density: Option<f64>,
+    palette: Option<PathBuf>,
+    interpolation: Option<Interpolation>,
+    palette_map: Option<PaletteMap>,
+    formula: Option<Formula>,
+    coloring: Option<Coloring>,
+    layers: Option<Vec<Layer>>,
+    shading: Option<Shading>,
+    dither: Option<Dither>,
+    output: Option<PathBuf>,
+    tile: Option<usize>,
+    data: Option<PathBuf>,
+    cycle: Option<usize>,
+    delay: Option<u16>,
+}
+
+pub fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let Some(options) = parse_args(env::args().skip(1)).map_err(|e| eyre!("{}\n\n{}", e, USAGE))? else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+
+    render(options).map_err(|e| eyre!(e))
+}
+
+/// Returns `None` when help was asked for.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", arg));
+
+        match arg.as_str() {
+            "-l" | "--location" => options.location = Some(value()?.into()),
+            "--re" => options.re = Some(value()?),
+            "--im" => options.im = Some(value()?),
+            "--radius" => options.radius = Some(value()?),
+            "--rotation" => options.rotation = Some(parse_number(&value()?)?),
+            "-s" | "--size" => options.size = Some(parse_size(&value()?)?),
+            "-i" | "--maxiter" => options.maxiter = Some(parse_number(&value()?)?),
+            "--bailout" => options.bailout = Some(parse_number(&value()?)?),
+            "--stripe-density" => options.stripe_density = Some(parse_number(&value()?)?),
+            "-p" | "--palette" => options.palette = Some(value()?.into()),
+            "--interpolation" => options.interpolation = Some(Interpolation::from_name(&value()?)?),
+            "--palette-map" => options.palette_map = Some(location::parse_palette_map(&value()?)?),
+            "-f" | "--formula" => options.formula = Some(Formula::from_name(&value()?)?),
+            "--coloring" => options.coloring = Some(Coloring::from_name(&value()?)?),
+            "--layer" => options.layers
+                .get_or_insert_with(Vec::new)
+                .push(location::parse_layer(&value()?)?),
+            "--shade" => options.shading = Some(location::parse_shading(&value()?)?),
+            "--dither" => options.dither = Some(Dither::from_name(&value()?)?),
+            "-o" | "--output" => options.output = Some(value()?.into()),
+            "-d" | "--data" => options.data = Some(value()?.into()),
+            "-c" | "--cycle" => options.cycle = Some(parse_number(&value()?)?),
+            "--delay" => options.delay = Some(parse_number(&value()?)?),
+            "-t" | "--tile" => options.tile = Some(parse_number(&value()?)?),
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    Ok(Some(options))
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text
+        .parse()
+        .map_err(|_| format!("Invalid number \"{}\"", text))
+}
+
+fn parse_size(text: &str) -> Result<(usize, usize), String> {
+    text
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .ok_or_else(|| format!("Invalid size \"{}\", expected <width>x<height>", text))
+}
+
+fn render(options: Options) -> Result<(), String> {
+    let output = options.output.ok_or_else(|| "No output file given".to_string())?;
+    let location = match &options.location {
+        Some(path) => {
+            let (w, h) = options.size.unwrap_or((800, 600));
+            let (location, warnings) = Location::<Real>::open(path, w, h)?;
+            for warning in warnings {
+                eprintln!("warning: {}", warning);
+            }
+            Some(location)
+        },
+        None => None,
+    };
+
+    let (w, h) = options.size
+        .or(location.as_ref().map(|l| l.sector.size()))
+        .unwrap_or((800, 600));
+    let (re, im, radius) = location
+        .as_ref()
+        .map(|l| l.center_radius())
+        .unwrap_or(("0".to_string(), "0".to_string(), "2".to_string()));
+    let (re, im, radius) = (
+        options.re.unwrap_or(re),
+        options.im.unwrap_or(im),
+        options.radius.unwrap_or(radius),
+    );
+    let rotation = options.rotation
+        .map(f64::to_radians)
+        .or(location.as_ref().map(|l| l.sector.rotation()))
+        .unwrap_or(0.0);
+    let sector = Sector::<Real>::from_center_radius(&re, &im, &radius, w, h)?.rotate(rotation);
+    let maxiter = options.maxiter
+        .or(location.as_ref().map(|l| l.maxiter))
+        .unwrap_or(20000);
+    let bailout = options.bailout.or(location.as_ref().and_then(|l| l.bailout));
+    let stripe_density = options.stripe_density.or(location.as_ref().and_then(|l| l.stripe_density));
+    let formula = options.formula
+        .or(location.as_ref().map(|l| l.formula))
+        .unwrap_or_default();
+    let coloring = options.coloring
+        .or(location.as_ref().map(|l| l.coloring))
+        .unwrap_or_default();
+    let layers = options.layers
+        .or(location.as_ref().map(|l| l.layers.clone()))
+        .unwrap_or_default();
+    let shading = options.shading.or(location.as_ref().and_then(|l| l.shading));
+    let (mut palette, palette_name) = match (&options.palette, &location) {
+        (Some(path), _) => (
+            palette::read_palette(path)?,
+            path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+        ),
+        (None, Some(location)) => (location.load_palette()?, location.palette_name.clone()),
+        (None, None) => (Gradient::default(), "black and white".to_string()),
+    };
+    if let Some(interpolation) = options.interpolation {
+        palette.interpolation = interpolation;
+    }
+    let palette_map = options.palette_map
+        .or(location.as_ref().map(|l| l.palette_map))
+        .unwrap_or_default();
+
+    let metadata = Location {
+        sector: sector.clone(),
+        coordinates: Some((re, im, radius)),
+        formula,
+        maxiter,
+        bailout,
+        stripe_density,
+        coloring,
+        layers: layers.clone(),
+        shading,
+        palette: PaletteSource::Embedded(palette.clone()),
+        palette_map,
+        palette_name,
+    };
+    let (re, im, radius) = metadata.center_radius();
+    eprintln!(
+        "Rendering {} at {} {} radius {}, {}x{} pixels, {} iterations",
+        formula.name(), re, im, radius, w, h, maxiter
+    );
+    let text = metadata.to_png_text();
+    let compute_options = metadata.compute_options();
+    let runtime = Runtime::new().map_err(|e| e.to_string())?;
+
+    if options.cycle.is_some() && options.data.is_some() {
+        return Err("Data layers can't be written with palette cycling animations".to_string());
+    }
+
+    if let Some(tile_size) = options.tile {
+        if options.data.is_some() {
+            return Err("Data layers can't be written when rendering in tiles".to_string());
+        }
+        if options.cycle.is_some() {
+            return Err("Palette cycling animations can't be rendered in tiles".to_string());
+        }
+
+        let writer = ImageWriter::create(&output, w, h, &text)?;
+        let ct = CancellationToken::new();
+        let start = Instant::now();
+        let result = runtime.block_on(async {
+            tokio::spawn({
+                let ct = ct.clone();
+                async move {
+                    if tokio::signal::ctrl_c().await.is_ok() {
+                        ct.cancel();
+                    }
+                }
+            });
+
+            poster::export_poster(
+                &sector,
+                compute_options,
+                (
+                    colorizer::layered(coloring, &layers, shading, palette_map, &palette).as_mut(),
+                    options.dither.unwrap_or_default()
+                ),
+                tile_size,
+                writer,
+                ct,
+                |done, total| eprint!(
+                    "\rTile {}/{}{}{}",
+                    done,
+                    total,
+                    format_remaining(mandelbrot::estimate_remaining(start.elapsed(), done, total)),
+                    CLEAR_LINE
+                )
+            ).await
+        });
+        eprintln!();
+
+        if result.is_err() {
+            _ = fs::remove_file(&output);
+        }
+        return result;
+    }
+
+    let (progress, mut receiver) = watch::channel(Progress::default());
+    let mandelbrot_set = runtime
+        .block_on(async {
+            let printer = tokio::spawn(async move {
+                let mut printed = None;
+                while receiver.changed().await.is_ok() {
+                    let progress = *receiver.borrow_and_update();
+                    print_progress(&progress);
+                    printed = Some(progress);
+                    tokio::time::sleep(PROGRESS_INTERVAL).await;
+                }
+                // The last rows may have finished while sleeping
+                let last = *receiver.borrow();
+                if printed != Some(last) {
+                    print_progress(&last);
+                }
+                eprintln!();
+            });
+            let set = sector.compute_with_progress(compute_options, CancellationToken::new(), progress).await;
+            _ = printer.await;
+            set
+        })
+        .ok_or_else(|| "Computation interrupted".to_string())?;
+
+    if let Some(frames) = options.cycle {
+        return animation::write_palette_cycle(
+            &output,
+            &mandelbrot_set,
+            &palette,
+            |cycled| colorizer::layered(coloring, &layers, shading, palette_map, cycled),
+            frames,
+            options.delay.unwrap_or(50),
+            &text
+        );
+    }
+
+    let image = colorizer::colorize_dithered(
+        &mandelbrot_set,
+        colorizer::layered(coloring, &layers, shading, palette_map, &palette).as_mut(),
+        options.dither.unwrap_or_default()
+    );
+
+    if let Some(data) = &options.data {
+        raw::write_raw(data, &mandelbrot_set)?;
+    }
+
+    export::write_image(&output, &image, w, h, &text)
+}
+
+fn print_progress(progress: &Progress) {
+    eprint!(
+        "\rPixels {}/{} ({:.0}%), {} iterations{}{}",
+        progress.pixels,
+        progress.total,
+        progress.fraction() * 100.0,
+        progress.iterations,
+        format_remaining(progress.remaining()),
+        CLEAR_LINE
+    );
+}
+
+fn format_remaining(remaining: Option<Duration>) -> String {
+    remaining
+        .map(|remaining| format!(", {:.1} s left", remaining.as_secs_f64()))
+        .unwrap_or_default()
+}
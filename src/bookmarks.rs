@@ -0,0 +1,193 @@
+//! Bookmark libraries: a TOML file holding a `[[bookmark]]` array,
+//! every bookmark being a `Location` with a name, a creation time
+//! in seconds since the Unix epoch and a small thumbnail.
+
+use std::{
+    env,
+    fs,
+    io,
+    path::{ Path, PathBuf },
+    time::{ SystemTime, UNIX_EPOCH },
+};
+use toml::{ Table, Value };
+use crate::gradient;
+use crate::location::{ self, Location };
+use crate::mandelbrot::Arithmetic;
+
+/// A small top-down RGB picture of a bookmarked view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    pub w: usize,
+    pub h: usize,
+    pub pixels: Vec<(u8, u8, u8)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark<Real: Arithmetic> {
+    pub name: String,
+    pub timestamp: SystemTime,
+    pub location: Location<Real>,
+    pub thumbnail: Thumbnail,
+}
+
+/// A collection of bookmarks, stored in a single file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Library<Real: Arithmetic> {
+    pub path: PathBuf,
+    pub bookmarks: Vec<Bookmark<Real>>,
+}
+
+/// Where the library lives when the user didn't pick one.
+pub fn default_library_path() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(".mandelbrot-bookmarks.toml")
+}
+
+impl Thumbnail {
+    /// Shrinks an image, stored bottom-up as `MandelbrotSetWithHistogram`
+    /// does, to `w` pixels wide by averaging blocks of pixels in
+    /// linear light.
+    pub fn from_image(image: &[(u8, u8, u8)], image_w: usize, image_h: usize, w: usize) -> Self {
+        let h = (w * image_h / image_w.max(1)).max(1);
+        let mut pixels = Vec::with_capacity(w * h);
+        let linear = gradient::srgb_to_linear_table();
+
+        for ty in 0..h {
+            // Thumbnails are top-down
+            let (y0, y1) = block(h - ty - 1, h, image_h);
+            for tx in 0..w {
+                let (x0, x1) = block(tx, w, image_w);
+                let mut sum = [0.0f32; 3];
+                for y in y0..y1 {
+                    for &(r, g, b) in &image[y * image_w + x0..y * image_w + x1] {
+                        for (sum, c) in sum.iter_mut().zip([r, g, b]) {
+                            *sum += linear[c as usize];
+                        }
+                    }
+                }
+                let count = ((y1 - y0) * (x1 - x0)).max(1) as f32;
+                pixels.push(gradient::linear_to_rgb8(sum.map(|s| s / count)));
+            }
+        }
+
+        Self { w, h, pixels }
+    }
+
+    pub fn to_rgb24(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect()
+    }
+}
+
+/// Range of source pixels covered by the `i`-th of `n` blocks.
+fn block(i: usize, n: usize, len: usize) -> (usize, usize) {
+    let start = i * len / n;
+    (start, ((i + 1) * len / n).max(start + 1).min(len))
+}
+
+impl<Real: Arithmetic> Library<Real> {
+    /// Reads a library, a missing file is an empty library.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Error reading {}: {}", path.to_string_lossy(), e)),
+        };
+        let root = text
+            .parse::<Table>()
+            .map_err(|e| e.to_string())?;
+        let bookmarks = match root.get("bookmark") {
+            None => Vec::new(),
+            Some(Value::Array(bookmarks)) => bookmarks
+                .iter()
+                .map(|b| b
+                    .as_table()
+                    .ok_or_else(|| "Bookmarks must be tables".to_string())
+                    .and_then(Bookmark::from_table)
+                )
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err("\"bookmark\" must be an array of tables".to_string()),
+        };
+
+        Ok(Self { path: path.to_owned(), bookmarks })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut root = Table::new();
+        root.insert("bookmark".into(), self.bookmarks
+            .iter()
+            .map(|b| Value::from(b.to_table()))
+            .collect::<Vec<Value>>()
+            .into()
+        );
+
+        fs::write(&self.path, root.to_string())
+            .map_err(|e| format!("Error writing {}: {}", self.path.to_string_lossy(), e))
+    }
+}
+
+impl<Real: Arithmetic> Bookmark<Real> {
+    fn from_table(table: &Table) -> Result<Self, String> {
+        let thumbnail = location::table(table, "thumbnail")?;
+        let w = location::integer(thumbnail, "width")?;
+        let h = location::integer(thumbnail, "height")?;
+        let bytes = decode_hex(location::string(thumbnail, "pixels")?)?;
+
+        if bytes.len() != w * h * 3 {
+            return Err("Thumbnail size doesn't match its pixels".to_string());
+        }
+
+        Ok(Self {
+            name: location::string(table, "name")?.to_string(),
+            timestamp: UNIX_EPOCH + std::time::Duration::from_secs(
+                location::integer(table, "timestamp")? as u64
+            ),
+            location: Location::from_table(table)?,
+            thumbnail: Thumbnail {
+                w,
+                h,
+                pixels: bytes.chunks(3).map(|c| (c[0], c[1], c[2])).collect(),
+            },
+        })
+    }
+
+    fn to_table(&self) -> Table {
+        let mut thumbnail = Table::new();
+        thumbnail.insert("width".into(), (self.thumbnail.w as i64).into());
+        thumbnail.insert("height".into(), (self.thumbnail.h as i64).into());
+        thumbnail.insert("pixels".into(), self.thumbnail
+            .to_rgb24()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+            .into()
+        );
+
+        let mut table = self.location.to_table();
+        table.insert("name".into(), self.name.as_str().into());
+        table.insert("timestamp".into(), (self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default() as i64).into()
+        );
+        table.insert("thumbnail".into(), thumbnail.into());
+        table
+    }
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    text
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| std::str::from_utf8(pair)
+            .ok()
+            .filter(|pair| pair.len() == 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            .ok_or_else(|| "Invalid hexadecimal string".to_string())
+        )
+        .collect()
+}
@@ -0,0 +1,664 @@
+//! Colorings as composable objects: each one turns the data of a
+//! pixel into an RGBA color, after looking at the whole result once,
+//! and several of them can be layered with blend modes.
+//!
+//! Colorings using a gradient give the color at 0 to interior points,
+//! and place escaping points so that the ones escaping first get the
+//! color at 1.
+
+use std::{
+    f32::consts::TAU,
+    thread,
+};
+use crate::dither::Dither;
+use crate::gradient::{ self, Gradient };
+use crate::mandelbrot::{ Coloring, MandelbrotSetWithHistogram, Orbit, Pixel };
+
+/// Colors pixels, with RGBA channels between 0 and 1.
+pub trait Colorizer: Send + Sync {
+    /// Looks at the whole result before any pixel is colored, for
+    /// colorings depending on all pixels such as histogram equalization.
+    fn prepare(&mut self, _set: &MandelbrotSetWithHistogram) {}
+
+    /// `orbit` only holds the values computed for the colorings
+    /// of the view, see `ComputeOptions`.
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4];
+}
+
+/// Prepares `colorizer` for `set` and colors all of its pixels, alpha
+/// covering black. Preparing is cheap for the built-in colorizers, so
+/// that the view can be colored again whenever the gradient changes.
+pub fn colorize(set: &MandelbrotSetWithHistogram, colorizer: &mut dyn Colorizer) -> Vec<(u8, u8, u8)> {
+    colorize_dithered(set, colorizer, Dither::None)
+}
+
+/// Same as `colorize`, rounding colors to 8 bits with `dither`.
+pub fn colorize_dithered(
+    set: &MandelbrotSetWithHistogram,
+    colorizer: &mut dyn Colorizer,
+    dither: Dither
+) -> Vec<(u8, u8, u8)> {
+    colorizer.prepare(set);
+    quantize(set, colorizer, dither, (0, 0))
+}
+
+/// Colors all pixels of `set` with an already prepared `colorizer`,
+/// such as one prepared for a whole image that `set` is the tile
+/// at `origin` of, with y growing upwards. Tiles are dithered as
+/// that part of the whole image would be.
+pub fn colorize_prepared(
+    set: &MandelbrotSetWithHistogram,
+    colorizer: &dyn Colorizer,
+    dither: Dither,
+    origin: (usize, usize)
+) -> Vec<(u8, u8, u8)> {
+    quantize(set, colorizer, dither, origin)
+}
+
+fn quantize(
+    set: &MandelbrotSetWithHistogram,
+    colorizer: &dyn Colorizer,
+    dither: Dither,
+    (x, y): (usize, usize)
+) -> Vec<(u8, u8, u8)> {
+    let channel = |c: f32, offset: f32| (c.clamp(0.0, 1.0) * 255.0 + offset).round().clamp(0.0, 255.0) as u8;
+    let (w, _) = set.size();
+    let pixels = set.pixels();
+    let mut image = vec![(0, 0, 0); pixels.len()];
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = pixels.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        for (start, (pixels, image)) in pixels.chunks(chunk).zip(image.chunks_mut(chunk)).enumerate() {
+            scope.spawn(move || {
+                for (i, (pixel, color)) in pixels.iter().zip(image).enumerate() {
+                    let index = start * chunk + i;
+                    let offset = dither.offset(x + index % w.max(1), y + index / w.max(1));
+                    let [r, g, b, a] = colorizer.color(pixel, &set.orbit(index));
+                    *color = (channel(r * a, offset), channel(g * a, offset), channel(b * a, offset));
+                }
+            });
+        }
+    });
+
+    image
+}
+
+fn rgba([r, g, b]: [f32; 3]) -> [f32; 4] {
+    [r, g, b, 1.0]
+}
+
+/// How the histogram coloring lays the gradient over escaping points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaletteMap {
+    /// Shift along the gradient, wrapping around, 1 being a whole gradient.
+    pub offset: f32,
+    /// Number of times the gradient is gone through.
+    pub repeat: f32,
+    /// Room given to the points escaping first.
+    pub density: Mapping,
+    /// Goes through the gradient from the other end.
+    pub reverse: bool,
+    /// Places points by their iteration count relative to the maximum
+    /// number of iterations instead of equalizing the histogram.
+    pub proportional: bool,
+}
+
+impl Default for PaletteMap {
+    /// The gradient once, equalized.
+    fn default() -> Self {
+        Self { offset: 0.0, repeat: 1.0, density: Mapping::Linear, reverse: false, proportional: false }
+    }
+}
+
+impl PaletteMap {
+    /// Iteration counts spread over this many before the logarithm.
+    const LOG_SCALE: f32 = 100.0;
+
+    /// Position on the gradient of points at `fraction` of the way
+    /// from the first escaping points to the last ones.
+    pub fn position(&self, fraction: f32) -> f32 {
+        let fraction = match self.density {
+            Mapping::Linear => fraction,
+            Mapping::Log => (fraction * Self::LOG_SCALE).ln_1p() / Self::LOG_SCALE.ln_1p(),
+            Mapping::Sqrt => fraction.sqrt(),
+        };
+        let position = (1.0 - fraction) * self.repeat + self.offset;
+        // The end of a repetition stays at the end of the gradient
+        let position = match position.rem_euclid(1.0) {
+            wrapped if wrapped == 0.0 && position > 0.0 => 1.0,
+            wrapped => wrapped,
+        };
+
+        if self.reverse { 1.0 - position } else { position }
+    }
+}
+
+/// Histogram equalization: escaping points are placed on the gradient
+/// by the fraction of escaping points escaping before them, so that
+/// colors are spread evenly whatever the view and however much of it
+/// is inside the set. The placement can be changed with a `PaletteMap`.
+pub struct Histogram {
+    gradient: Gradient,
+    map: PaletteMap,
+    /// Color of each iteration count, looked up for every pixel.
+    colors: Vec<[f32; 4]>,
+    interior: [f32; 4],
+}
+
+impl Histogram {
+    pub fn new(gradient: Gradient) -> Self {
+        Self::with_map(gradient, PaletteMap::default())
+    }
+
+    pub fn with_map(gradient: Gradient, map: PaletteMap) -> Self {
+        let interior = rgba(gradient.sample_f32(0.0));
+        Self { gradient, map, colors: Vec::new(), interior }
+    }
+
+    /// Prepares for a cumulative histogram, see
+    /// `MandelbrotSetWithHistogram::cumulative_histogram`, which may be
+    /// the one of an image larger than the sets colored afterwards.
+    pub fn prepare_cumulative(&mut self, cumulative: &[usize]) {
+        let escaped = cumulative.last().copied().unwrap_or(0).max(1) as f32;
+        let maxiter = cumulative.len().saturating_sub(1).max(1) as f32;
+        self.colors = (0..cumulative.len())
+            .map(|i| {
+                let fraction = if self.map.proportional {
+                    i as f32 / maxiter
+                } else {
+                    (if i > 0 { cumulative[i - 1] } else { 0 }) as f32 / escaped
+                };
+                rgba(self.gradient.sample_f32(self.map.position(fraction)))
+            })
+            .collect();
+    }
+}
+
+impl Colorizer for Histogram {
+    fn prepare(&mut self, set: &MandelbrotSetWithHistogram) {
+        self.prepare_cumulative(set.cumulative_histogram());
+    }
+
+    fn color(&self, pixel: &Pixel, _orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return self.interior;
+        }
+
+        self.colors
+            .get(pixel.iterations)
+            .copied()
+            .unwrap_or(self.interior)
+    }
+}
+
+/// How iteration counts are spread over the gradient by `IterationMap`,
+/// or escaping points by a `PaletteMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mapping {
+    Linear,
+    /// Gives more room to the points escaping first.
+    Log,
+    Sqrt,
+}
+
+impl Mapping {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mapping::Linear => "linear",
+            Mapping::Log => "log",
+            Mapping::Sqrt => "sqrt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "linear" => Ok(Mapping::Linear),
+            "log" => Ok(Mapping::Log),
+            "sqrt" => Ok(Mapping::Sqrt),
+            _ => Err(format!("Unknown density \"{}\"", name)),
+        }
+    }
+}
+
+/// Places points on the gradient by their iteration count relative
+/// to the maximum number of iterations.
+pub struct IterationMap {
+    gradient: Gradient,
+    mapping: Mapping,
+    maxiter: usize,
+}
+
+impl IterationMap {
+    pub fn new(gradient: Gradient, mapping: Mapping) -> Self {
+        Self { gradient, mapping, maxiter: 1 }
+    }
+
+    fn map(&self, iterations: f32) -> f32 {
+        match self.mapping {
+            Mapping::Linear => iterations,
+            Mapping::Log => iterations.ln_1p(),
+            Mapping::Sqrt => iterations.sqrt(),
+        }
+    }
+}
+
+impl Colorizer for IterationMap {
+    fn prepare(&mut self, set: &MandelbrotSetWithHistogram) {
+        self.maxiter = set.maxiter().max(1);
+    }
+
+    fn color(&self, pixel: &Pixel, _orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        let position = self.map(pixel.iterations as f32) / self.map(self.maxiter as f32);
+        rgba(self.gradient.sample_f32(1.0 - position))
+    }
+}
+
+/// Goes through the gradient and back every `2 * period` smooth
+/// iterations, giving a point the same color in every view.
+pub struct Smooth {
+    gradient: Gradient,
+    period: f32,
+}
+
+impl Smooth {
+    pub fn new(gradient: Gradient, period: f32) -> Self {
+        Self { gradient, period: period.max(f32::EPSILON) }
+    }
+}
+
+impl Colorizer for Smooth {
+    fn color(&self, pixel: &Pixel, _orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        rgba(self.gradient.sample_mirrored_f32(pixel.smooth / self.period))
+    }
+}
+
+/// Colors by the estimated distance to the set, outlining its
+/// filaments: points `falloff` pixels away are about two thirds
+/// of the way along the gradient.
+pub struct Distance {
+    gradient: Gradient,
+    falloff: f32,
+}
+
+impl Distance {
+    pub fn new(gradient: Gradient, falloff: f32) -> Self {
+        Self { gradient, falloff: falloff.max(f32::EPSILON) }
+    }
+}
+
+impl Colorizer for Distance {
+    fn color(&self, pixel: &Pixel, _orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        rgba(self.gradient.sample_f32(1.0 - (-pixel.distance / self.falloff).exp()))
+    }
+}
+
+/// Colors by the argument of the last value of the orbit, going
+/// through the gradient and back around the circle.
+pub struct Angle {
+    gradient: Gradient,
+}
+
+impl Angle {
+    pub fn new(gradient: Gradient) -> Self {
+        Self { gradient }
+    }
+}
+
+impl Colorizer for Angle {
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        rgba(self.gradient.sample_mirrored_f32(2.0 * turn(orbit)))
+    }
+}
+
+/// The argument of the last value of the orbit, in turns from 0 to 1.
+fn turn(orbit: &Orbit) -> f32 {
+    (orbit.z.1.atan2(orbit.z.0) / TAU).rem_euclid(1.0)
+}
+
+/// Splits the circle in `sectors` equal parts, starting from the
+/// positive real axis, and gives each part a color evenly spaced on
+/// the gradient: two sectors is binary decomposition, by the sign of
+/// the imaginary part.
+pub struct Decomposition {
+    gradient: Gradient,
+    sectors: usize,
+}
+
+impl Decomposition {
+    pub fn new(gradient: Gradient, sectors: usize) -> Self {
+        Self { gradient, sectors: sectors.max(2) }
+    }
+}
+
+impl Colorizer for Decomposition {
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        let sector = ((turn(orbit) * self.sectors as f32) as usize).min(self.sectors - 1);
+        rgba(self.gradient.sample_f32(1.0 - sector as f32 / (self.sectors - 1) as f32))
+    }
+}
+
+/// Draws `lines` field lines, along which the external angle is
+/// constant, in the color at 0 over the color at 1. Lines split in
+/// two at every iteration closer to the set. `width` is the fraction
+/// of the space between two lines they cover.
+pub struct FieldLines {
+    gradient: Gradient,
+    lines: usize,
+    width: f32,
+}
+
+impl FieldLines {
+    pub fn new(gradient: Gradient, lines: usize, width: f32) -> Self {
+        Self { gradient, lines: lines.max(1), width: width.clamp(f32::EPSILON, 1.0) }
+    }
+}
+
+impl Colorizer for FieldLines {
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        // Distance to the nearest line, in spaces between lines
+        let offset = turn(orbit) * self.lines as f32;
+        let distance = (offset - offset.round()).abs();
+        rgba(self.gradient.sample_f32(2.0 * distance / self.width))
+    }
+}
+
+/// Colors by the stripe average of the orbit, see `Orbit::stripe`.
+pub struct Stripe {
+    gradient: Gradient,
+}
+
+impl Stripe {
+    pub fn new(gradient: Gradient) -> Self {
+        Self { gradient }
+    }
+}
+
+impl Colorizer for Stripe {
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        rgba(self.gradient.sample_f32(orbit.stripe))
+    }
+}
+
+/// Colors by the triangle inequality average of the orbit, see `Orbit::tia`.
+pub struct Tia {
+    gradient: Gradient,
+}
+
+impl Tia {
+    pub fn new(gradient: Gradient) -> Self {
+        Self { gradient }
+    }
+}
+
+impl Colorizer for Tia {
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4] {
+        if pixel.interior {
+            return rgba(self.gradient.sample_f32(0.0));
+        }
+
+        rgba(self.gradient.sample_f32(orbit.tia))
+    }
+}
+
+/// How a layer is combined with the layers below it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The layer covers the ones below.
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+    Darken,
+    Lighten,
+    Difference,
+}
+
+impl BlendMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Multiply => "multiply",
+            BlendMode::Screen => "screen",
+            BlendMode::Overlay => "overlay",
+            BlendMode::Add => "add",
+            BlendMode::Darken => "darken",
+            BlendMode::Lighten => "lighten",
+            BlendMode::Difference => "difference",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "normal" => Ok(BlendMode::Normal),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            "add" => Ok(BlendMode::Add),
+            "darken" => Ok(BlendMode::Darken),
+            "lighten" => Ok(BlendMode::Lighten),
+            "difference" => Ok(BlendMode::Difference),
+            _ => Err(format!("Unknown blend mode \"{}\"", name)),
+        }
+    }
+
+    /// Blends channel `top` over channel `base`.
+    fn blend(&self, base: f32, top: f32) -> f32 {
+        match self {
+            BlendMode::Normal => top,
+            BlendMode::Multiply => base * top,
+            BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - top),
+            BlendMode::Overlay => if base < 0.5 {
+                2.0 * base * top
+            } else {
+                1.0 - 2.0 * (1.0 - base) * (1.0 - top)
+            },
+            BlendMode::Add => (base + top).min(1.0),
+            BlendMode::Darken => base.min(top),
+            BlendMode::Lighten => base.max(top),
+            BlendMode::Difference => (base - top).abs(),
+        }
+    }
+}
+
+/// A coloring drawn over the ones before it, as stored in locations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layer {
+    pub coloring: Coloring,
+    pub blend: BlendMode,
+    /// Between 0 for an invisible layer and 1.
+    pub opacity: f32,
+}
+
+/// Colorizers drawn one over the other, the first one at the bottom,
+/// blended in linear light.
+pub struct Layers {
+    base: Box<dyn Colorizer>,
+    layers: Vec<(Box<dyn Colorizer>, BlendMode, f32)>,
+}
+
+impl Layers {
+    pub fn new(base: Box<dyn Colorizer>) -> Self {
+        Self { base, layers: Vec::new() }
+    }
+
+    pub fn with_layer(mut self, colorizer: Box<dyn Colorizer>, blend: BlendMode, opacity: f32) -> Self {
+        self.layers.push((colorizer, blend, opacity.clamp(0.0, 1.0)));
+        self
+    }
+}
+
+impl Colorizer for Layers {
+    fn prepare(&mut self, set: &MandelbrotSetWithHistogram) {
+        self.base.prepare(set);
+        for (colorizer, _, _) in &mut self.layers {
+            colorizer.prepare(set);
+        }
+    }
+
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4] {
+        let [r, g, b, alpha] = self.base.color(pixel, orbit);
+        let base = (gradient::srgb_to_linear([r, g, b]), alpha);
+        let (color, alpha) = self.layers.iter().fold(base, |(base, alpha), (colorizer, blend, opacity)| {
+            let [r, g, b, a] = colorizer.color(pixel, orbit);
+            let top = gradient::srgb_to_linear([r, g, b]);
+            let coverage = a * opacity;
+            (
+                [0, 1, 2].map(|i| base[i] + (blend.blend(base[i], top[i]) - base[i]) * coverage),
+                alpha + coverage * (1.0 - alpha)
+            )
+        });
+        let [r, g, b] = gradient::linear_to_srgb(color);
+        [r, g, b, alpha]
+    }
+}
+
+/// A light shining on the set, for 3D shading, see `Shaded`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shading {
+    /// Direction the light comes from, in degrees counterclockwise
+    /// from the right of the image.
+    pub azimuth: f32,
+    /// Height of the light in degrees, 90 shining straight down.
+    pub elevation: f32,
+}
+
+impl Default for Shading {
+    /// From the top left.
+    fn default() -> Self {
+        Self { azimuth: 135.0, elevation: 45.0 }
+    }
+}
+
+/// Lights the colors of another colorizer as if the set were a
+/// relief rising towards it, with Lambert and Blinn-Phong shading.
+/// Normals come from the derivative of the orbit, see `Orbit::normal`,
+/// tilted 45 degrees towards the set; interior points aren't shaded.
+pub struct Shaded {
+    colorizer: Box<dyn Colorizer>,
+    light: [f32; 3],
+    /// Halfway between the light and the viewer, above the image.
+    halfway: [f32; 3],
+}
+
+impl Shaded {
+    const AMBIENT: f32 = 0.25;
+    const DIFFUSE: f32 = 0.75;
+    const SPECULAR: f32 = 0.3;
+    const SHININESS: i32 = 32;
+
+    pub fn new(colorizer: Box<dyn Colorizer>, shading: Shading) -> Self {
+        let (azimuth, elevation) = (shading.azimuth.to_radians(), shading.elevation.to_radians());
+        let light = [
+            azimuth.cos() * elevation.cos(),
+            azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+        ];
+        let halfway = normalize([light[0], light[1], light[2] + 1.0]);
+        Self { colorizer, light, halfway }
+    }
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length > 0.0 { v.map(|c| c / length) } else { [0.0, 0.0, 1.0] }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+impl Colorizer for Shaded {
+    fn prepare(&mut self, set: &MandelbrotSetWithHistogram) {
+        self.colorizer.prepare(set);
+    }
+
+    fn color(&self, pixel: &Pixel, orbit: &Orbit) -> [f32; 4] {
+        let [r, g, b, a] = self.colorizer.color(pixel, orbit);
+        if pixel.interior {
+            return [r, g, b, a];
+        }
+
+        let normal = normalize([orbit.normal.0, orbit.normal.1, 1.0]);
+        let diffuse = dot(normal, self.light).max(0.0);
+        let specular = dot(normal, self.halfway).max(0.0).powi(Self::SHININESS);
+        let light = Self::AMBIENT + Self::DIFFUSE * diffuse;
+        let highlight = Self::SPECULAR * specular;
+        [r * light + highlight, g * light + highlight, b * light + highlight, a]
+    }
+}
+
+impl Coloring {
+    /// The colorizer of this coloring, with its default settings
+    /// except for the palette map of the histogram coloring.
+    pub fn colorizer(&self, gradient: &Gradient, map: PaletteMap) -> Box<dyn Colorizer> {
+        let gradient = gradient.clone();
+        match self {
+            Coloring::Histogram => Box::new(Histogram::with_map(gradient, map)),
+            Coloring::Linear => Box::new(IterationMap::new(gradient, Mapping::Linear)),
+            Coloring::Log => Box::new(IterationMap::new(gradient, Mapping::Log)),
+            Coloring::Sqrt => Box::new(IterationMap::new(gradient, Mapping::Sqrt)),
+            Coloring::Smooth => Box::new(Smooth::new(gradient, 64.0)),
+            Coloring::Distance => Box::new(Distance::new(gradient, 4.0)),
+            Coloring::Angle => Box::new(Angle::new(gradient)),
+            Coloring::Stripe => Box::new(Stripe::new(gradient)),
+            Coloring::Tia => Box::new(Tia::new(gradient)),
+            Coloring::Binary => Box::new(Decomposition::new(gradient, 2)),
+            Coloring::Decomposition => Box::new(Decomposition::new(gradient, 8)),
+            Coloring::FieldLines => Box::new(FieldLines::new(gradient, 1, 0.2)),
+        }
+    }
+}
+
+/// The colorizer of a location: its coloring with `layers` over it,
+/// all using `gradient` laid out by `map`, and lit by `shading` if any.
+pub fn layered(
+    coloring: Coloring,
+    layers: &[Layer],
+    shading: Option<Shading>,
+    map: PaletteMap,
+    gradient: &Gradient
+) -> Box<dyn Colorizer> {
+    let colorizer = if layers.is_empty() {
+        coloring.colorizer(gradient, map)
+    } else {
+        Box::new(layers.iter().fold(Layers::new(coloring.colorizer(gradient, map)), |stack, layer| {
+            stack.with_layer(layer.coloring.colorizer(gradient, map), layer.blend, layer.opacity)
+        }))
+    };
+
+    match shading {
+        Some(shading) => Box::new(Shaded::new(colorizer, shading)),
+        None => colorizer,
+    }
+}
@@ -0,0 +1,160 @@
+//! Dithering when colors are rounded to 8 bits per channel, which
+//! breaks up the bands of slowly changing gradients: each pixel gets
+//! an offset of less than half a step, from a pattern tiling the image.
+
+use std::sync::OnceLock;
+
+/// Side of the blue noise pattern, in pixels.
+const NOISE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Plain rounding.
+    #[default]
+    None,
+    /// 8 by 8 Bayer matrix, a regular cross-hatched pattern.
+    Ordered,
+    /// Noise without low frequencies, least visible but slower to set up.
+    BlueNoise,
+}
+
+impl Dither {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dither::None => "none",
+            Dither::Ordered => "ordered",
+            Dither::BlueNoise => "blue-noise",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "none" => Ok(Dither::None),
+            "ordered" => Ok(Dither::Ordered),
+            "blue-noise" => Ok(Dither::BlueNoise),
+            _ => Err(format!("Unknown dithering \"{}\"", name)),
+        }
+    }
+
+    /// Offset added to the pixel at `x`, `y` before rounding, between
+    /// -0.5 and 0.5 in 8 bits steps.
+    pub fn offset(&self, x: usize, y: usize) -> f32 {
+        match self {
+            Dither::None => 0.0,
+            Dither::Ordered => (bayer(x % 8, y % 8) as f32 + 0.5) / 64.0 - 0.5,
+            Dither::BlueNoise => blue_noise()[(y % NOISE_SIZE) * NOISE_SIZE + x % NOISE_SIZE],
+        }
+    }
+}
+
+/// Rank of `x`, `y` in the 8 by 8 Bayer matrix, the lowest bits of the
+/// coordinates giving the highest bits of the rank.
+fn bayer(x: usize, y: usize) -> usize {
+    (0..3).fold(0, |rank, bit| {
+        let (x, y) = ((x >> bit) & 1, (y >> bit) & 1);
+        rank | ((((x ^ y) << 1) | y) << (2 * (2 - bit)))
+    })
+}
+
+/// Offsets of the blue noise pattern, computed the first time they are needed.
+fn blue_noise() -> &'static [f32] {
+    static NOISE: OnceLock<Vec<f32>> = OnceLock::new();
+    NOISE.get_or_init(void_and_cluster)
+}
+
+/// A binary pattern wrapping around the edges, and how crowded each
+/// pixel is by the set pixels around it.
+#[derive(Clone)]
+struct Pattern {
+    set: Vec<bool>,
+    energy: Vec<f32>,
+}
+
+impl Pattern {
+    /// Sets or clears pixel `i`, `kernel` giving the weight of each offset.
+    fn toggle(&mut self, i: usize, kernel: &[f32]) {
+        self.set[i] = !self.set[i];
+        let sign = if self.set[i] { 1.0 } else { -1.0 };
+        let (x, y) = (i % NOISE_SIZE, i / NOISE_SIZE);
+        for (j, energy) in self.energy.iter_mut().enumerate() {
+            let dx = (j % NOISE_SIZE + NOISE_SIZE - x) % NOISE_SIZE;
+            let dy = (j / NOISE_SIZE + NOISE_SIZE - y) % NOISE_SIZE;
+            *energy += sign * kernel[dy * NOISE_SIZE + dx];
+        }
+    }
+
+    /// The most crowded set pixel, or the least crowded clear one.
+    fn extreme(&self, set: bool) -> usize {
+        let pixels = (0..self.set.len()).filter(|&i| self.set[i] == set);
+        let energy = |&i: &usize| self.energy[i];
+        if set {
+            pixels.max_by(|a, b| energy(a).total_cmp(&energy(b))).unwrap_or(0)
+        } else {
+            pixels.min_by(|a, b| energy(a).total_cmp(&energy(b))).unwrap_or(0)
+        }
+    }
+}
+
+/// Blue noise with Ulichney's void and cluster method: pixels are
+/// ranked by setting them one at a time where the pattern is the least
+/// crowded, starting from a sparse pattern whose pixels are spread out
+/// and ranked by clearing them where it is the most crowded.
+fn void_and_cluster() -> Vec<f32> {
+    const PIXELS: usize = NOISE_SIZE * NOISE_SIZE;
+    const SIGMA: f32 = 1.5;
+
+    // Gaussian of the distance, wrapping around
+    let kernel: Vec<f32> = (0..PIXELS)
+        .map(|i| {
+            let wrap = |d: usize| d.min(NOISE_SIZE - d) as f32;
+            let (dx, dy) = (wrap(i % NOISE_SIZE), wrap(i / NOISE_SIZE));
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+
+    // A tenth of the pixels, picked with a fixed seed
+    let mut initial = Pattern { set: vec![false; PIXELS], energy: vec![0.0; PIXELS] };
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut count = 0;
+    while count < PIXELS / 10 {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        let i = (seed >> 33) as usize % PIXELS;
+        if !initial.set[i] {
+            initial.toggle(i, &kernel);
+            count += 1;
+        }
+    }
+
+    // Moves the most crowded pixel to the largest void until it stays put
+    loop {
+        let cluster = initial.extreme(true);
+        initial.toggle(cluster, &kernel);
+        let void = initial.extreme(false);
+        initial.toggle(void, &kernel);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; PIXELS];
+    let mut pattern = initial.clone();
+    for rank in (0..count).rev() {
+        let cluster = pattern.extreme(true);
+        pattern.toggle(cluster, &kernel);
+        ranks[cluster] = rank;
+    }
+
+    // The least crowded clear pixel is also the most crowded one of
+    // the clear pixels, once they are fewer than the set ones
+    let mut pattern = initial;
+    for rank in count..PIXELS {
+        let void = pattern.extreme(false);
+        pattern.toggle(void, &kernel);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| (rank as f32 + 0.5) / PIXELS as f32 - 0.5)
+        .collect()
+}
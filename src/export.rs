@@ -0,0 +1,105 @@
+//! Writing rendered images to files.
+
+use std::{
+    fs::File,
+    io::{ self, BufWriter, Write },
+    path::{ Path, PathBuf },
+};
+use crate::png::PngWriter;
+
+/// An image file written one row at a time, top to bottom,
+/// in the format matching the extension of its path.
+pub struct ImageWriter {
+    path: PathBuf,
+    format: Format,
+}
+
+enum Format {
+    Png(PngWriter<BufWriter<File>>),
+    /// Binary Netpbm, a header followed by top-down RGB triplets.
+    Ppm(BufWriter<File>),
+}
+
+impl ImageWriter {
+    /// Creates the file and writes its header, `text` is stored
+    /// as metadata by formats supporting it.
+    pub fn create(
+        path: &Path,
+        w: usize,
+        h: usize,
+        text: &[(String, String)]
+    ) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if extension != "png" && extension != "ppm" {
+            return Err(format!("Unsupported image format: {}", path.to_string_lossy()));
+        }
+
+        if w == 0 || h == 0 {
+            return Err("Nothing to export".to_string());
+        }
+
+        let file = File::create(path)
+            .map_err(|e| format!("Error creating {}: {}", path.to_string_lossy(), e))?;
+        let mut writer = BufWriter::new(file);
+        let format = if extension == "png" {
+            PngWriter::new(writer, w, h, text).map(Format::Png)
+        } else {
+            write!(writer, "P6\n{} {}\n255\n", w, h).map(|_| Format::Ppm(writer))
+        };
+
+        Ok(Self {
+            format: format.map_err(|e| write_error(path, e))?,
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn write_row(&mut self, row: &[(u8, u8, u8)]) -> Result<(), String> {
+        match &mut self.format {
+            Format::Png(png) => png.write_row(row),
+            Format::Ppm(writer) => {
+                let bytes: Vec<u8> = row
+                    .iter()
+                    .flat_map(|&(r, g, b)| [r, g, b])
+                    .collect();
+                writer.write_all(&bytes)
+            },
+        }
+            .map_err(|e| write_error(&self.path, e))
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self.format {
+            Format::Png(png) => png.finish(),
+            Format::Ppm(writer) => Ok(writer),
+        }
+            .and_then(|mut writer| writer.flush())
+            .map_err(|e| write_error(&self.path, e))
+    }
+}
+
+fn write_error(path: &Path, e: io::Error) -> String {
+    format!("Error writing {}: {}", path.to_string_lossy(), e)
+}
+
+/// Writes an image, stored bottom-up as returned by
+/// `MandelbrotSetWithHistogram::get_image_from_gradient`,
+/// see `ImageWriter`.
+pub fn write_image(
+    path: &Path,
+    image: &[(u8, u8, u8)],
+    w: usize,
+    h: usize,
+    text: &[(String, String)]
+) -> Result<(), String> {
+    let mut writer = ImageWriter::create(path, w, h, text)?;
+
+    for row in image.chunks(w).take(h).rev() {
+        writer.write_row(row)?;
+    }
+
+    writer.finish()
+}
@@ -0,0 +1,280 @@
+//! Gradients: colors placed at positions between 0 and 1, with
+//! the colors in between interpolated.
+
+/// How colors between two stops are computed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Straight lines between sRGB values.
+    Rgb,
+    /// Straight lines between linear light values, brighter midpoints.
+    #[default]
+    LinearRgb,
+    /// Hue, saturation and value, hue taking the shortest way round.
+    Hsv,
+    /// Perceptually uniform, see <https://bottosson.github.io/posts/oklab/>.
+    Oklab,
+    /// Catmull-Rom spline through all sRGB stops, smooth across them.
+    Spline,
+}
+
+impl Interpolation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Interpolation::Rgb => "rgb",
+            Interpolation::LinearRgb => "linear-rgb",
+            Interpolation::Hsv => "hsv",
+            Interpolation::Oklab => "oklab",
+            Interpolation::Spline => "spline",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "rgb" => Ok(Interpolation::Rgb),
+            "linear-rgb" => Ok(Interpolation::LinearRgb),
+            "hsv" => Ok(Interpolation::Hsv),
+            "oklab" => Ok(Interpolation::Oklab),
+            "spline" => Ok(Interpolation::Spline),
+            _ => Err(format!("Unknown interpolation \"{}\"", name)),
+        }
+    }
+}
+
+/// Color stops sorted by position, and how to interpolate them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    stops: Vec<(f32, (u8, u8, u8))>,
+    pub interpolation: Interpolation,
+}
+
+impl Default for Gradient {
+    /// Black to white.
+    fn default() -> Self {
+        Self::from_colors(&[(0, 0, 0), (255, 255, 255)])
+    }
+}
+
+impl Gradient {
+    /// Positions are clamped between 0 and 1, stops don't need
+    /// to be sorted.
+    pub fn new(
+        stops: &[(f32, (u8, u8, u8))],
+        interpolation: Interpolation
+    ) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err("A gradient needs at least one color".to_string());
+        }
+
+        if stops.iter().any(|(position, _)| position.is_nan()) {
+            return Err("Invalid color position".to_string());
+        }
+
+        let mut stops: Vec<_> = stops
+            .iter()
+            .map(|&(position, color)| (position.clamp(0.0, 1.0), color))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(Self { stops, interpolation })
+    }
+
+    /// Evenly spaced stops, from 0 to 1, as GIMP palettes are converted,
+    /// with the default interpolation.
+    pub fn from_colors(colors: &[(u8, u8, u8)]) -> Self {
+        let last = colors.len().max(2) - 1;
+        let stops = if colors.is_empty() {
+            vec![(0.0, (0, 0, 0))]
+        } else {
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, &color)| (i as f32 / last as f32, color))
+                .collect()
+        };
+
+        Self { stops, interpolation: Interpolation::default() }
+    }
+
+    pub fn stops(&self) -> &[(f32, (u8, u8, u8))] {
+        &self.stops
+    }
+
+    pub fn colors(&self) -> Vec<(u8, u8, u8)> {
+        self.stops
+            .iter()
+            .map(|&(_, color)| color)
+            .collect()
+    }
+
+    /// Whether stops are evenly spaced from 0 to 1, as `from_colors` places them.
+    pub fn is_even(&self) -> bool {
+        *self == Self {
+            interpolation: self.interpolation,
+            ..Self::from_colors(&self.colors())
+        }
+    }
+
+    /// The color at `t`, clamped between 0 and 1.
+    pub fn sample(&self, t: f32) -> (u8, u8, u8) {
+        to_rgb(self.sample_f32(t))
+    }
+
+    /// The color at `t` with sRGB channels between 0 and 1, not
+    /// rounded to 8 bits so that it can be dithered.
+    pub fn sample_f32(&self, t: f32) -> [f32; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let next = self.stops.partition_point(|&(position, _)| position <= t);
+
+        if next == 0 {
+            return from_rgb(self.stops[0].1);
+        }
+        if next == self.stops.len() {
+            return from_rgb(self.stops[next - 1].1);
+        }
+
+        let (p0, c0) = self.stops[next - 1];
+        let (p1, c1) = self.stops[next];
+        let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 0.0 };
+
+        match self.interpolation {
+            Interpolation::Rgb => lerp(from_rgb(c0), from_rgb(c1), f),
+            Interpolation::LinearRgb => linear_to_srgb(lerp(
+                srgb_to_linear(from_rgb(c0)),
+                srgb_to_linear(from_rgb(c1)),
+                f
+            )),
+            Interpolation::Hsv => {
+                let (a, b) = (rgb_to_hsv(from_rgb(c0)), rgb_to_hsv(from_rgb(c1)));
+                // Shortest way round the hue circle
+                let turn = (b[0] - a[0] + 0.5).rem_euclid(1.0) - 0.5;
+                let mut hsv = lerp(a, b, f);
+                hsv[0] = (a[0] + turn * f).rem_euclid(1.0);
+                hsv_to_rgb(hsv)
+            },
+            Interpolation::Oklab => linear_to_srgb(oklab_to_linear(lerp(
+                linear_to_oklab(srgb_to_linear(from_rgb(c0))),
+                linear_to_oklab(srgb_to_linear(from_rgb(c1))),
+                f
+            ))).map(|c| c.min(1.0)),
+            Interpolation::Spline => self.spline(next - 1, f).map(|c| c.clamp(0.0, 1.0)),
+        }
+    }
+
+    /// Goes through the gradient and back every 2 units of `t`, which
+    /// is continuous for any gradient when cycling colors.
+    pub fn sample_mirrored(&self, t: f32) -> (u8, u8, u8) {
+        to_rgb(self.sample_mirrored_f32(t))
+    }
+
+    pub fn sample_mirrored_f32(&self, t: f32) -> [f32; 3] {
+        self.sample_f32(1.0 - (t.rem_euclid(2.0) - 1.0).abs())
+    }
+
+    /// Cubic Hermite interpolation between stops `i` and `i + 1`,
+    /// with Catmull-Rom tangents allowing for uneven spacing.
+    fn spline(&self, i: usize, f: f32) -> [f32; 3] {
+        let point = |j: usize| (self.stops[j].0, from_rgb(self.stops[j].1));
+        let tangent = |j: usize| {
+            let (before, after) = (j.saturating_sub(1), (j + 1).min(self.stops.len() - 1));
+            let ((p0, c0), (p1, c1)) = (point(before), point(after));
+            let dp = p1 - p0;
+            [0, 1, 2].map(|k| if dp > 0.0 { (c1[k] - c0[k]) / dp } else { 0.0 })
+        };
+
+        let ((p0, c0), (p1, c1)) = (point(i), point(i + 1));
+        let (m0, m1) = (tangent(i), tangent(i + 1));
+        let dp = p1 - p0;
+        let (f2, f3) = (f * f, f * f * f);
+
+        [0, 1, 2].map(|k| {
+            (2.0 * f3 - 3.0 * f2 + 1.0) * c0[k]
+                + (f3 - 2.0 * f2 + f) * dp * m0[k]
+                + (-2.0 * f3 + 3.0 * f2) * c1[k]
+                + (f3 - f2) * dp * m1[k]
+        })
+    }
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], f: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * f)
+}
+
+fn from_rgb((r, g, b): (u8, u8, u8)) -> [f32; 3] {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
+}
+
+fn to_rgb(color: [f32; 3]) -> (u8, u8, u8) {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    (channel(color[0]), channel(color[1]), channel(color[2]))
+}
+
+/// Linear light value of each 8 bits sRGB channel value, decoding
+/// is too slow to be done for every pixel when averaging images.
+pub fn srgb_to_linear_table() -> [f32; 256] {
+    std::array::from_fn(|i| srgb_to_linear([i as f32 / 255.0; 3])[0])
+}
+
+/// Encodes a linear light color back to 8 bits sRGB.
+pub fn linear_to_rgb8(color: [f32; 3]) -> (u8, u8, u8) {
+    to_rgb(linear_to_srgb(color))
+}
+
+pub fn srgb_to_linear(color: [f32; 3]) -> [f32; 3] {
+    color.map(|c| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) })
+}
+
+pub fn linear_to_srgb(color: [f32; 3]) -> [f32; 3] {
+    color.map(|c| {
+        let c = c.max(0.0);
+        if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+    })
+}
+
+pub fn rgb_to_hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+
+    [hue / 6.0, if max > 0.0 { delta / max } else { 0.0 }, max]
+}
+
+pub fn hsv_to_rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let channel = |n: f32| {
+        let k = (n + h * 6.0).rem_euclid(6.0);
+        v - v * s * k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+    [channel(5.0), channel(3.0), channel(1.0)]
+}
+
+fn linear_to_oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let l = (0.412_221_47 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+fn oklab_to_linear([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let l_ = l + 0.396_337_78 * a + 0.215_803_76 * b;
+    let m_ = l - 0.105_561_346 * a - 0.063_854_17 * b;
+    let s_ = l - 0.089_484_18 * a - 1.291_485_5 * b;
+    let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+
+    [
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    ]
+}
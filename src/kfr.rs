@@ -0,0 +1,208 @@
+//! Kalles Fraktaler `.kfr` location files.
+//!
+//! These are lists of `Key: Value` lines; we map `Re`, `Im`, `Zoom`,
+//! `Iterations`, `Rotate` and `Colors` and report anything else that
+//! would change the picture as a warning. A zoom of 1 shows a radius of 2.
+//! The center keeps all the digits of the file, see `Location::coordinates`.
+
+use crate::colorizer::PaletteMap;
+use crate::gradient::Gradient;
+use crate::location::{ Location, PaletteSource };
+use crate::palette;
+use crate::mandelbrot::{ Arithmetic, Coloring, Formula, Sector };
+
+/// Keys that only affect how Kalles Fraktaler computes the picture,
+/// or that hold the values we use for them anyway.
+const HARMLESS_KEYS: &[&str] = &[
+    "Re", "Im", "Zoom", "Iterations", "Rotate", "Colors",
+    "FractalType", "Power", "Ratio", "Smooth", "ColorOffset",
+    "InteriorColor", "Version", "Period", "SeedR", "SeedI",
+    "FactorAR", "FactorAI",
+];
+
+pub fn parse<Real: Arithmetic>(
+    text: &str,
+    w: usize,
+    h: usize
+) -> Result<(Location<Real>, Vec<String>), String> {
+    let mut warnings = Vec::new();
+    let fields: Vec<(&str, &str)> = text
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .collect();
+    let get = |key: &str| fields
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| *v);
+    let require = |key: &str| get(key).ok_or_else(|| format!("Missing \"{}\"", key));
+    let number = |key: &str| -> Result<Real, String> {
+        let value = require(key)?;
+        value
+            .parse::<Real>()
+            .map_err(|_| format!("Invalid {}: \"{}\"", key, value))
+    };
+
+    let zoom = number("Zoom")?;
+    if zoom.partial_cmp(&0.0f32.into()) != Some(std::cmp::Ordering::Greater) {
+        return Err("Zoom must be greater than zero".to_string());
+    }
+
+    let mut sector = Sector::new(
+        (number("Re")?, number("Im")?),
+        Real::from(2.0f32) / zoom,
+        w,
+        h
+    );
+
+    if let Some(angle) = get("Rotate") {
+        let angle = angle
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid Rotate: \"{}\"", angle))?;
+        sector = sector.rotate(angle.to_radians());
+    }
+
+    let maxiter = require("Iterations")?
+        .parse::<usize>()
+        .map_err(|_| "Invalid Iterations".to_string())?;
+
+    let (palette, palette_name) = match get("Colors") {
+        Some(colors) => (PaletteSource::Embedded(palette::flat(&parse_colors(colors)?)), "Kalles Fraktaler"),
+        None => {
+            warnings.push("No colors, using a black and white palette".to_string());
+            (PaletteSource::Embedded(Gradient::default()), "black and white")
+        },
+    };
+
+    if get("FractalType").unwrap_or("0") != "0" || get("Power").unwrap_or("2") != "2" {
+        warnings.push(format!(
+            "Fractal type {} with power {} is not supported, rendering the Mandelbrot set",
+            get("FractalType").unwrap_or("0"),
+            get("Power").unwrap_or("2")
+        ));
+    }
+
+    if get("Ratio").and_then(|r| r.parse::<f64>().ok()).is_some_and(|r| r != 360.0) {
+        warnings.push("Stretched views (Ratio) are not supported".to_string());
+    }
+
+    warnings.extend(fields
+        .iter()
+        .filter(|(k, _)| !HARMLESS_KEYS.contains(k))
+        .map(|(k, v)| format!("Ignoring {}: {}", k, v))
+    );
+
+    let (_, _, radius) = sector.center_radius();
+
+    Ok((
+        Location {
+            coordinates: Some((require("Re")?.to_string(), require("Im")?.to_string(), radius)),
+            sector,
+            formula: Formula::Mandelbrot,
+            maxiter,
+            bailout: None,
+            stripe_density: None,
+            coloring: Coloring::Histogram,
+            layers: Vec::new(),
+            shading: None,
+            palette,
+            palette_map: PaletteMap::default(),
+            palette_name: palette_name.to_string(),
+        },
+        warnings
+    ))
+}
+
+/// Writes a location as `.kfr`, palettes stored in files
+/// can't be referenced and are left out, gradients lose their
+/// positions and interpolation.
+pub fn write<Real: Arithmetic>(location: &Location<Real>) -> String {
+    let (re, im, _) = location.center_radius();
+    let zoom = format!("{:e}", Real::from(2.0f32) / location.sector.radius()).to_uppercase();
+    let colors: String = match &location.palette {
+        PaletteSource::Embedded(gradient) => gradient
+            .colors()
+            .into_iter()
+            .map(|(r, g, b)| format!("{},{},{},", r, g, b))
+            .collect(),
+        PaletteSource::File(_) => String::new(),
+    };
+
+    format!(
+        "Re: {}\r\nIm: {}\r\nZoom: {}\r\nIterations: {}\r\nRotate: {}\r\nRatio: 360\r\nColors: {}\r\n",
+        re,
+        im,
+        zoom,
+        location.maxiter,
+        location.sector.rotation().to_degrees(),
+        colors
+    )
+}
+
+/// Colors are stored as a flat list of comma separated
+/// red, green and blue values.
+fn parse_colors(text: &str) -> Result<Vec<(u8, u8, u8)>, String> {
+    let channels = text
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|c| c.parse::<u8>().map_err(|_| format!("Invalid color component \"{}\"", c)))
+        .collect::<Result<Vec<u8>, String>>()?;
+
+    if channels.len() < 3 || channels.len() % 3 != 0 {
+        return Err("Colors must be a list of red, green, blue triplets".to_string());
+    }
+
+    Ok(channels.chunks(3).map(|c| (c[0], c[1], c[2])).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KFR: &str = "\
+Re: -1.74999841099374081749002483162428393452822172335808534616943930976364725846655540417646727085571962736578151132907961927190726789896685696750162524460775546580822744596887978637416593715319388030232414667046419863755743802\r\n\
+Im: -0.00000000000000165712469295418692325810961981279189026504290127375760405334498110850956047368308707050735960323397389547038231194872482690340369921750514146922400928554011996123112902000856666847088788158433995358406\r\n\
+Zoom: 1.6E190\r\n\
+Iterations: 200000\r\n\
+Rotate: 0\r\n\
+Ratio: 360\r\n\
+Colors: 0,0,0,255,128,0,255,255,255,\r\n";
+
+    #[test]
+    fn round_trip() {
+        let (location, warnings) = parse::<f64>(KFR, 640, 480).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(location.maxiter, 200000);
+        assert_eq!(
+            location.load_palette().unwrap().colors(),
+            vec![(0, 0, 0), (255, 128, 0), (255, 255, 255)]
+        );
+
+        // The center keeps the digits f64 can't hold
+        let text = write(&location);
+        assert!(text.contains(KFR.lines().next().unwrap()));
+        assert!(text.contains(KFR.lines().nth(1).unwrap()));
+
+        let (parsed, warnings) = parse::<f64>(&text, 640, 480).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(parsed, location);
+    }
+
+    #[test]
+    fn unsupported_fields_are_reported() {
+        let text = format!("{}FractalType: 4\r\nSlopes: 1\r\n", KFR);
+        let (_, warnings) = parse::<f64>(&text, 640, 480).unwrap();
+        assert_eq!(warnings, vec![
+            "Fractal type 4 with power 2 is not supported, rendering the Mandelbrot set".to_string(),
+            "Ignoring Slopes: 1".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(parse::<f64>("Re: 0\r\nIm: 0\r\n", 640, 480), Err("Missing \"Zoom\"".to_string()));
+        assert!(parse::<f64>(&KFR.replace("Zoom: 1.6E190", "Zoom: 0"), 640, 480).is_err());
+        assert!(parse::<f64>(&KFR.replace("255,255,255,", "255,255,"), 640, 480).is_err());
+    }
+}
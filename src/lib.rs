@@ -0,0 +1,18 @@
+//! Mandelbrot set computation, coloring and file formats,
+//! shared by the explorer and the command line renderer.
+
+pub mod animation;
+pub mod bookmarks;
+pub mod colorizer;
+pub mod dither;
+pub mod export;
+pub mod gradient;
+pub mod kfr;
+pub mod location;
+pub mod mandelbrot;
+pub mod movie;
+pub mod palette;
+pub mod png;
+pub mod poster;
+pub mod raw;
+pub mod xpf;
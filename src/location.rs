@@ -0,0 +1,855 @@
+//! Parameter files describing a view, in TOML:
+//!
+//! ```toml
+//! [view]
+//! re = "-0.743643887037151"
+//! im = "0.131825904205330"
+//! radius = "1.5e-10"
+//! rotation = 0.0 # degrees
+//! width = 800
+//! height = 600
+//!
+//! [render]
+//! formula = "mandelbrot"
+//! maxiter = 20000
+//! coloring = "histogram"
+//! # optional colorings drawn over it, opacity 1 by default:
+//! layers = [{ coloring = "distance", blend = "multiply", opacity = 0.5 }]
+//! # optional, the one the colorings need by default:
+//! bailout = 1024.0
+//! # optional, number of stripes of the stripe coloring, 5 by default:
+//! stripe_density = 5.0
+//!
+//! # optional, lights the image as a relief, see `colorizer::Shaded`:
+//! [shading]
+//! azimuth = 135.0 # degrees counterclockwise from the right
+//! elevation = 45.0 # degrees above the image
+//!
+//! [palette]
+//! name = "black and white"
+//! colors = ["#000000", "#ffffff"]
+//! # optional, evenly spaced from 0 to 1 by default:
+//! positions = [0.0, 1.0]
+//! # rgb, linear-rgb, hsv, oklab or spline, rgb when missing
+//! # as files from before linear-rgb:
+//! interpolation = "rgb"
+//! # optional, how the histogram coloring lays the gradient out,
+//! # see `colorizer::PaletteMap`:
+//! offset = 0.0
+//! repeat = 1.0
+//! density = "linear" # linear, log or sqrt
+//! reverse = false
+//! proportional = false
+//! # or, relative to the parameter file:
+//! # path = "palettes/fire.gpl"
+//! ```
+//!
+//! Coordinates are strings so that they keep all of their digits,
+//! they are written back as they were read as long as the view
+//! doesn't move, see `Location::coordinates`.
+
+use std::{
+    fs,
+    io,
+    path::{ Path, PathBuf },
+};
+use toml::{ Table, Value };
+use crate::colorizer::{ BlendMode, Layer, Mapping, PaletteMap, Shading };
+use crate::gradient::{ Gradient, Interpolation };
+use crate::mandelbrot::{ Arithmetic, Coloring, ComputeOptions, Formula, Sector, LARGE_BAILOUT };
+use crate::png::{ self, PngInfo };
+use crate::{ kfr, palette, xpf };
+
+/// Where the palette of a `Location` comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteSource {
+    /// A gradient stored in the parameter file itself.
+    Embedded(Gradient),
+    /// A palette file, in any format of the `palette` module.
+    File(PathBuf),
+}
+
+/// Everything needed to render a view again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location<Real: Arithmetic> {
+    pub sector: Sector<Real>,
+    /// The decimal center and radius `sector` was parsed from, with
+    /// the digits `Real` can't hold, see `center_radius`.
+    pub coordinates: Option<(String, String, String)>,
+    pub formula: Formula,
+    pub maxiter: usize,
+    /// Bailout radius, instead of the one the colorings need,
+    /// see `ComputeOptions::bailout`.
+    pub bailout: Option<f64>,
+    /// Number of stripes of the stripe average, instead of
+    /// `DEFAULT_STRIPE_DENSITY`, see `ComputeOptions::stripe_density`.
+    pub stripe_density: Option<f64>,
+    pub coloring: Coloring,
+    /// Colorings drawn over `coloring`, see `colorizer::layered`.
+    pub layers: Vec<Layer>,
+    pub shading: Option<Shading>,
+    pub palette: PaletteSource,
+    pub palette_map: PaletteMap,
+    pub palette_name: String,
+}
+
+impl<Real: Arithmetic> Location<Real> {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        Self::from_table(&text
+            .parse::<Table>()
+            .map_err(|e| e.to_string())?
+        )
+    }
+
+    /// Reads a location from the `view`, `render`
+    /// and `palette` tables of `root`.
+    pub fn from_table(root: &Table) -> Result<Self, String> {
+        let view = table(root, "view")?;
+        let render = table(root, "render")?;
+        let palette = table(root, "palette")?;
+
+        let (re, im, radius) = (string(view, "re")?, string(view, "im")?, string(view, "radius")?);
+        let sector = Sector::from_center_radius(
+            re,
+            im,
+            radius,
+            integer(view, "width")?,
+            integer(view, "height")?
+        )?.rotate(float(view, "rotation")?.to_radians());
+
+        let source = match (palette.get("colors"), palette.get("path")) {
+            (Some(Value::Array(colors)), None) => {
+                let colors = colors
+                    .iter()
+                    .map(|c| c
+                        .as_str()
+                        .ok_or_else(|| "Palette colors must be strings".to_string())
+                        .and_then(parse_color)
+                    )
+                    .collect::<Result<Vec<_>, _>>()?;
+                let positions = match palette.get("positions") {
+                    Some(Value::Array(positions)) => Some(positions
+                        .iter()
+                        .map(|p| match p {
+                            Value::Float(f) => Ok(*f as f32),
+                            Value::Integer(i) => Ok(*i as f32),
+                            _ => Err("Palette positions must be numbers".to_string()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                    ),
+                    Some(_) => return Err("\"positions\" must be an array".to_string()),
+                    None => None,
+                };
+                let interpolation = match palette.get("interpolation") {
+                    Some(name) => Interpolation::from_name(name
+                        .as_str()
+                        .ok_or_else(|| "\"interpolation\" must be a string".to_string())?
+                    )?,
+                    None => Interpolation::Rgb,
+                };
+                PaletteSource::Embedded(gradient(&colors, positions.as_deref(), interpolation)?)
+            },
+            (None, Some(Value::String(path))) => PaletteSource::File(path.into()),
+            _ => return Err(
+                "Palette needs either a \"colors\" array or a \"path\"".to_string()
+            ),
+        };
+        let palette_name = match (palette.get("name"), &source) {
+            (Some(name), _) => name
+                .as_str()
+                .ok_or_else(|| "\"name\" must be a string".to_string())?
+                .to_string(),
+            (None, PaletteSource::File(path)) => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            (None, PaletteSource::Embedded(_)) => "embedded".to_string(),
+        };
+        let defaults = PaletteMap::default();
+        let palette_map = PaletteMap {
+            offset: match palette.get("offset") {
+                Some(_) => float(palette, "offset")? as f32,
+                None => defaults.offset,
+            },
+            repeat: match palette.get("repeat") {
+                Some(_) => float(palette, "repeat")? as f32,
+                None => defaults.repeat,
+            },
+            density: match palette.get("density") {
+                Some(_) => Mapping::from_name(string(palette, "density")?)?,
+                None => defaults.density,
+            },
+            reverse: match palette.get("reverse") {
+                Some(_) => boolean(palette, "reverse")?,
+                None => defaults.reverse,
+            },
+            proportional: match palette.get("proportional") {
+                Some(_) => boolean(palette, "proportional")?,
+                None => defaults.proportional,
+            },
+        };
+
+        Ok(Self {
+            sector,
+            coordinates: coordinates(re, im, radius),
+            formula: Formula::from_name(string(render, "formula")?)?,
+            maxiter: integer(render, "maxiter")?,
+            bailout: match render.get("bailout") {
+                Some(_) => Some(float(render, "bailout")?),
+                None => None,
+            },
+            stripe_density: match render.get("stripe_density") {
+                Some(_) => Some(float(render, "stripe_density")?),
+                None => None,
+            },
+            coloring: Coloring::from_name(string(render, "coloring")?)?,
+            layers: match render.get("layers") {
+                Some(Value::Array(layers)) => layers
+                    .iter()
+                    .map(|layer| layer
+                        .as_table()
+                        .ok_or_else(|| "Layers must be tables".to_string())
+                        .and_then(layer_from_table)
+                    )
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err("\"layers\" must be an array".to_string()),
+                None => Vec::new(),
+            },
+            shading: match root.get("shading") {
+                Some(Value::Table(shading)) => Some(Shading {
+                    azimuth: match shading.get("azimuth") {
+                        Some(_) => float(shading, "azimuth")? as f32,
+                        None => Shading::default().azimuth,
+                    },
+                    elevation: match shading.get("elevation") {
+                        Some(_) => float(shading, "elevation")? as f32,
+                        None => Shading::default().elevation,
+                    },
+                }),
+                Some(_) => return Err("\"shading\" must be a table".to_string()),
+                None => None,
+            },
+            palette: source,
+            palette_map,
+            palette_name,
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        self.to_table().to_string()
+    }
+
+    pub fn to_table(&self) -> Table {
+        let (re, im, radius) = self.center_radius();
+        let (w, h) = self.sector.size();
+
+        let mut view = Table::new();
+        view.insert("re".into(), re.into());
+        view.insert("im".into(), im.into());
+        view.insert("radius".into(), radius.into());
+        view.insert("rotation".into(), self.sector.rotation().to_degrees().into());
+        view.insert("width".into(), (w as i64).into());
+        view.insert("height".into(), (h as i64).into());
+
+        let mut render = Table::new();
+        render.insert("formula".into(), self.formula.name().into());
+        render.insert("maxiter".into(), (self.maxiter as i64).into());
+        if let Some(bailout) = self.bailout {
+            render.insert("bailout".into(), bailout.into());
+        }
+        if let Some(density) = self.stripe_density {
+            render.insert("stripe_density".into(), density.into());
+        }
+        render.insert("coloring".into(), self.coloring.name().into());
+        if !self.layers.is_empty() {
+            render.insert("layers".into(), self.layers
+                .iter()
+                .map(|layer| {
+                    let mut table = Table::new();
+                    table.insert("coloring".into(), layer.coloring.name().into());
+                    table.insert("blend".into(), layer.blend.name().into());
+                    table.insert("opacity".into(), (layer.opacity as f64).into());
+                    Value::from(table)
+                })
+                .collect::<Vec<Value>>()
+                .into()
+            );
+        }
+
+        let mut palette = Table::new();
+        palette.insert("name".into(), self.palette_name.as_str().into());
+        match &self.palette {
+            PaletteSource::Embedded(gradient) => {
+                palette.insert("colors".into(), gradient
+                    .colors()
+                    .into_iter()
+                    .map(|c| Value::from(format_color(c)))
+                    .collect::<Vec<Value>>()
+                    .into()
+                );
+                if !gradient.is_even() {
+                    palette.insert("positions".into(), gradient
+                        .stops()
+                        .iter()
+                        .map(|&(position, _)| Value::from(position as f64))
+                        .collect::<Vec<Value>>()
+                        .into()
+                    );
+                }
+                palette.insert("interpolation".into(), gradient.interpolation.name().into());
+            },
+            PaletteSource::File(path) => {
+                palette.insert("path".into(), path.to_string_lossy().as_ref().into());
+            },
+        }
+        if self.palette_map != PaletteMap::default() {
+            let map = self.palette_map;
+            palette.insert("offset".into(), (map.offset as f64).into());
+            palette.insert("repeat".into(), (map.repeat as f64).into());
+            palette.insert("density".into(), map.density.name().into());
+            palette.insert("reverse".into(), map.reverse.into());
+            palette.insert("proportional".into(), map.proportional.into());
+        }
+
+        let mut root = Table::new();
+        root.insert("view".into(), view.into());
+        root.insert("render".into(), render.into());
+        root.insert("palette".into(), palette.into());
+        if let Some(shading) = self.shading {
+            let mut table = Table::new();
+            table.insert("azimuth".into(), (shading.azimuth as f64).into());
+            table.insert("elevation".into(), (shading.elevation as f64).into());
+            root.insert("shading".into(), table.into());
+        }
+        root
+    }
+
+    /// Reads a parameter file, palette paths are made relative
+    /// to the directory containing it.
+    pub fn read_from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading {}: {}", path.to_string_lossy(), e))?;
+        let mut location = Self::parse(&text)?;
+
+        if let (PaletteSource::File(palette), Some(dir)) = (&mut location.palette, path.parent()) {
+            *palette = dir.join(&palette);
+        }
+
+        Ok(location)
+    }
+
+    /// Reads a parameter file, a Kalles Fraktaler `.kfr`, a XaoS `.xpf`
+    /// or the metadata of a PNG image depending on the extension.
+    /// `.kfr` and `.xpf` files don't store the image size, so `w` and `h`
+    /// are used instead, and anything that can't be mapped is described
+    /// in the returned warnings.
+    pub fn open(path: &Path, w: usize, h: usize) -> Result<(Self, Vec<String>), String> {
+        let read = || fs::read_to_string(path)
+            .map_err(|e| format!("Error reading {}: {}", path.to_string_lossy(), e));
+
+        match extension(path).as_str() {
+            "kfr" => kfr::parse(&read()?, w, h),
+            "xpf" => xpf::parse(&read()?, w, h),
+            "png" => Self::from_png_text(&png::read_info(
+                fs::File::open(path)
+                    .map(io::BufReader::new)
+                    .map_err(|e| format!("Error reading {}: {}", path.to_string_lossy(), e))?
+            )?),
+            _ => Ok((Self::read_from_file(path)?, Vec::new())),
+        }
+    }
+
+    /// Writes the location in the format matching the extension, see `open`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = match extension(path).as_str() {
+            "kfr" => kfr::write(self),
+            "xpf" => xpf::write(self),
+            _ => self.to_toml(),
+        };
+
+        fs::write(path, text)
+            .map_err(|e| format!("Error writing {}: {}", path.to_string_lossy(), e))
+    }
+
+    /// Center and radius as written to files: each of `coordinates`
+    /// as long as it still rounds to the value in `sector`, the digits
+    /// of `sector` otherwise.
+    pub fn center_radius(&self) -> (String, String, String) {
+        let rounded = self.sector.center_radius();
+        let Some((re, im, radius)) = &self.coordinates else {
+            return rounded;
+        };
+        let Ok(parsed) = Sector::<Real>::from_center_radius(re, im, radius, 1, 1)
+            .map(|sector| sector.center_radius()) else {
+            return rounded;
+        };
+        let pick = |exact: &String, parsed: String, rounded: String| {
+            if parsed == rounded { exact.clone() } else { rounded }
+        };
+
+        (
+            pick(re, parsed.0, rounded.0),
+            pick(im, parsed.1, rounded.1),
+            pick(radius, parsed.2, rounded.2),
+        )
+    }
+
+    /// Describes the location as PNG `tEXt` chunks.
+    pub fn to_png_text(&self) -> Vec<(String, String)> {
+        let (re, im, radius) = self.center_radius();
+        let mut text = vec![
+            ("Software".to_string(), "Mandelbrot Explorer".to_string()),
+            ("Center Re".to_string(), re),
+            ("Center Im".to_string(), im),
+            ("Radius".to_string(), radius),
+            ("Rotation".to_string(), self.sector.rotation().to_degrees().to_string()),
+            ("Formula".to_string(), self.formula.name().to_string()),
+            ("Iterations".to_string(), self.maxiter.to_string()),
+            ("Coloring".to_string(), self.coloring.name().to_string()),
+            ("Palette".to_string(), self.palette_name.clone()),
+        ];
+
+        if let Some(bailout) = self.bailout {
+            text.push(("Bailout".to_string(), bailout.to_string()));
+        }
+
+        if let Some(density) = self.stripe_density {
+            text.push(("Stripe Density".to_string(), density.to_string()));
+        }
+
+        if !self.layers.is_empty() {
+            text.push(("Layers".to_string(), self.layers
+                .iter()
+                .map(format_layer)
+                .collect::<Vec<String>>()
+                .join(", ")
+            ));
+        }
+
+        if let Some(shading) = self.shading {
+            text.push(("Shading".to_string(), format_shading(shading)));
+        }
+
+        if let PaletteSource::Embedded(gradient) = &self.palette {
+            text.push(("Palette Colors".to_string(), gradient
+                .colors()
+                .into_iter()
+                .map(format_color)
+                .collect::<Vec<String>>()
+                .join(" ")
+            ));
+            if !gradient.is_even() {
+                text.push(("Palette Positions".to_string(), gradient
+                    .stops()
+                    .iter()
+                    .map(|(position, _)| position.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+                ));
+            }
+            text.push((
+                "Palette Interpolation".to_string(),
+                gradient.interpolation.name().to_string()
+            ));
+        }
+
+        if self.palette_map != PaletteMap::default() {
+            text.push(("Palette Map".to_string(), format_palette_map(self.palette_map)));
+        }
+
+        text
+    }
+
+    /// Reads back the chunks written by `to_png_text`, only the
+    /// center and radius are required.
+    pub fn from_png_text(info: &PngInfo) -> Result<(Self, Vec<String>), String> {
+        let mut warnings = Vec::new();
+        let get = |key: &str| info.text
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str());
+        let require = |key: &str| get(key)
+            .ok_or_else(|| format!("The image has no \"{}\" metadata", key));
+
+        let rotation = match get("Rotation").map(str::parse::<f64>) {
+            Some(Ok(degrees)) => degrees.to_radians(),
+            Some(Err(_)) => return Err("Invalid rotation".to_string()),
+            None => 0.0,
+        };
+        let (re, im, radius) = (require("Center Re")?, require("Center Im")?, require("Radius")?);
+        let sector = Sector::from_center_radius(re, im, radius, info.w, info.h)?.rotate(rotation);
+        let maxiter = match get("Iterations") {
+            Some(i) => i.parse().map_err(|_| "Invalid iterations".to_string())?,
+            None => {
+                warnings.push("No iterations, using 20000".to_string());
+                20000
+            },
+        };
+        let bailout = get("Bailout")
+            .map(|b| b.parse().map_err(|_| "Invalid bailout".to_string()))
+            .transpose()?;
+        let stripe_density = get("Stripe Density")
+            .map(|d| d.parse().map_err(|_| "Invalid stripe density".to_string()))
+            .transpose()?;
+        let palette = match get("Palette Colors") {
+            Some(colors) => {
+                let colors = colors
+                    .split_whitespace()
+                    .map(parse_color)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let positions = get("Palette Positions")
+                    .map(|positions| positions
+                        .split_whitespace()
+                        .map(|p| p.parse().map_err(|_| format!("Invalid position \"{}\"", p)))
+                        .collect::<Result<Vec<f32>, _>>()
+                    )
+                    .transpose()?;
+                let interpolation = get("Palette Interpolation")
+                    .map(Interpolation::from_name)
+                    .transpose()?
+                    .unwrap_or(Interpolation::Rgb);
+                gradient(&colors, positions.as_deref(), interpolation)?
+            },
+            None => {
+                warnings.push("No palette colors, using black and white".to_string());
+                Gradient::default()
+            },
+        };
+
+        Ok((
+            Self {
+                sector,
+                coordinates: coordinates(re, im, radius),
+                formula: get("Formula").map(Formula::from_name).transpose()?.unwrap_or_default(),
+                maxiter,
+                bailout,
+                stripe_density,
+                coloring: get("Coloring").map(Coloring::from_name).transpose()?.unwrap_or_default(),
+                layers: match get("Layers") {
+                    Some(layers) => layers
+                        .split(',')
+                        .map(parse_layer)
+                        .collect::<Result<_, _>>()?,
+                    None => Vec::new(),
+                },
+                shading: get("Shading").map(parse_shading).transpose()?,
+                palette: PaletteSource::Embedded(palette),
+                palette_map: get("Palette Map")
+                    .map(parse_palette_map)
+                    .transpose()?
+                    .unwrap_or_default(),
+                palette_name: get("Palette").unwrap_or("embedded").to_string(),
+            },
+            warnings
+        ))
+    }
+
+    /// Options computing what the colorings of this location need.
+    pub fn compute_options(&self) -> ComputeOptions {
+        let colorings: Vec<Coloring> = std::iter::once(self.coloring)
+            .chain(self.layers.iter().map(|layer| layer.coloring))
+            .collect();
+        let mut options = ComputeOptions::for_colorings(self.maxiter, &colorings);
+        // Normals only line up across iteration counts with a large bailout
+        if self.shading.is_some() {
+            options.bailout = options.bailout.max(LARGE_BAILOUT);
+            options.normal = true;
+        }
+        if let Some(bailout) = self.bailout {
+            options.bailout = bailout;
+        }
+        options.stripe_density = options.stripe_density
+            .map(|density| self.stripe_density.unwrap_or(density));
+        options
+    }
+
+    pub fn load_palette(&self) -> Result<Gradient, String> {
+        match &self.palette {
+            PaletteSource::Embedded(gradient) => Ok(gradient.clone()),
+            PaletteSource::File(path) => palette::read_palette(path),
+        }
+    }
+}
+
+fn layer_from_table(table: &Table) -> Result<Layer, String> {
+    Ok(Layer {
+        coloring: Coloring::from_name(string(table, "coloring")?)?,
+        blend: match table.get("blend") {
+            Some(_) => BlendMode::from_name(string(table, "blend")?)?,
+            None => BlendMode::default(),
+        },
+        opacity: match table.get("opacity") {
+            Some(_) => float(table, "opacity")? as f32,
+            None => 1.0,
+        },
+    })
+}
+
+/// Parses a layer written as `coloring [blend [opacity]]`.
+pub fn parse_layer(text: &str) -> Result<Layer, String> {
+    let mut words = text.split_whitespace();
+    let coloring = words
+        .next()
+        .ok_or_else(|| "Empty layer".to_string())
+        .and_then(Coloring::from_name)?;
+    let blend = words
+        .next()
+        .map(BlendMode::from_name)
+        .transpose()?
+        .unwrap_or_default();
+    let opacity = match words.next() {
+        Some(opacity) => opacity
+            .parse()
+            .map_err(|_| format!("Invalid opacity \"{}\"", opacity))?,
+        None => 1.0,
+    };
+
+    Ok(Layer { coloring, blend, opacity })
+}
+
+pub fn format_layer(layer: &Layer) -> String {
+    format!("{} {} {}", layer.coloring.name(), layer.blend.name(), layer.opacity)
+}
+
+/// Parses a light written as `azimuth elevation`, in degrees.
+pub fn parse_shading(text: &str) -> Result<Shading, String> {
+    let invalid = || format!("Invalid shading \"{}\", expected \"azimuth elevation\"", text);
+    let (azimuth, elevation) = text
+        .trim()
+        .split_once(|c: char| c.is_whitespace() || c == ',')
+        .ok_or_else(invalid)?;
+    Ok(Shading {
+        azimuth: azimuth.trim().parse().map_err(|_| invalid())?,
+        elevation: elevation.trim().parse().map_err(|_| invalid())?,
+    })
+}
+
+pub fn format_shading(shading: Shading) -> String {
+    format!("{} {}", shading.azimuth, shading.elevation)
+}
+
+/// Parses a palette map written as `offset repeat [density] [reverse]
+/// [proportional]`, the words in any order.
+pub fn parse_palette_map(text: &str) -> Result<PaletteMap, String> {
+    let invalid = || format!(
+        "Invalid palette map \"{}\", expected \"offset repeat [density] [reverse] [proportional]\"",
+        text
+    );
+    let mut words = text.split_whitespace();
+    let mut map = PaletteMap {
+        offset: words.next().and_then(|w| w.parse().ok()).ok_or_else(invalid)?,
+        repeat: words.next().and_then(|w| w.parse().ok()).ok_or_else(invalid)?,
+        ..Default::default()
+    };
+    for word in words {
+        match word {
+            "reverse" => map.reverse = true,
+            "proportional" => map.proportional = true,
+            density => map.density = Mapping::from_name(density)?,
+        }
+    }
+    Ok(map)
+}
+
+pub fn format_palette_map(map: PaletteMap) -> String {
+    let mut text = format!("{} {} {}", map.offset, map.repeat, map.density.name());
+    if map.reverse {
+        text.push_str(" reverse");
+    }
+    if map.proportional {
+        text.push_str(" proportional");
+    }
+    text
+}
+
+fn coordinates(re: &str, im: &str, radius: &str) -> Option<(String, String, String)> {
+    Some((re.trim().to_string(), im.trim().to_string(), radius.trim().to_string()))
+}
+
+/// Stops at `positions`, or evenly spaced without them.
+fn gradient(
+    colors: &[(u8, u8, u8)],
+    positions: Option<&[f32]>,
+    interpolation: Interpolation
+) -> Result<Gradient, String> {
+    match positions {
+        Some(positions) if positions.len() != colors.len() => {
+            Err("Palette positions don't match its colors".to_string())
+        },
+        Some(positions) => Gradient::new(
+            &positions.iter().copied().zip(colors.iter().copied()).collect::<Vec<_>>(),
+            interpolation
+        ),
+        None => {
+            let mut gradient = Gradient::from_colors(colors);
+            gradient.interpolation = interpolation;
+            Ok(gradient)
+        },
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+pub fn table<'a>(parent: &'a Table, key: &str) -> Result<&'a Table, String> {
+    parent
+        .get(key)
+        .and_then(Value::as_table)
+        .ok_or_else(|| format!("Missing [{}] section", key))
+}
+
+pub fn string<'a>(parent: &'a Table, key: &str) -> Result<&'a str, String> {
+    parent
+        .get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("\"{}\" must be a string", key))
+}
+
+pub fn integer(parent: &Table, key: &str) -> Result<usize, String> {
+    parent
+        .get(key)
+        .and_then(Value::as_integer)
+        .and_then(|i| usize::try_from(i).ok())
+        .ok_or_else(|| format!("\"{}\" must be a positive integer", key))
+}
+
+/// Reads a float, missing values default to zero.
+fn float(parent: &Table, key: &str) -> Result<f64, String> {
+    match parent.get(key) {
+        None => Ok(0.0),
+        Some(Value::Float(f)) => Ok(*f),
+        Some(Value::Integer(i)) => Ok(*i as f64),
+        Some(_) => Err(format!("\"{}\" must be a number", key)),
+    }
+}
+
+fn boolean(parent: &Table, key: &str) -> Result<bool, String> {
+    parent
+        .get(key)
+        .and_then(Value::as_bool)
+        .ok_or_else(|| format!("\"{}\" must be true or false", key))
+}
+
+/// Parses a color written as `#rrggbb`.
+pub fn parse_color(text: &str) -> Result<(u8, u8, u8), String> {
+    let invalid = || format!("Invalid color \"{}\"", text);
+    let hex = text.strip_prefix('#').ok_or_else(invalid)?;
+
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
+
+pub fn format_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(palette: PaletteSource) -> Location<f64> {
+        let (re, im, radius) = (
+            "-0.743643887037158704752191506114774",
+            "0.131825904205311970493132056385139",
+            "1.5e-20"
+        );
+        Location {
+            sector: Sector::from_center_radius(re, im, radius, 640, 480)
+                .unwrap()
+                .rotate(30f64.to_radians()),
+            coordinates: Some((re.to_string(), im.to_string(), radius.to_string())),
+            formula: Formula::Mandelbrot,
+            maxiter: 5000,
+            bailout: Some(100.0),
+            stripe_density: Some(7.5),
+            coloring: Coloring::Smooth,
+            layers: vec![
+                Layer { coloring: Coloring::Distance, blend: BlendMode::Multiply, opacity: 0.5 },
+                Layer { coloring: Coloring::Stripe, blend: BlendMode::Overlay, opacity: 1.0 },
+            ],
+            shading: Some(Shading { azimuth: 135.0, elevation: 30.0 }),
+            palette,
+            palette_map: PaletteMap {
+                offset: 0.25,
+                repeat: 3.0,
+                density: Mapping::Sqrt,
+                reverse: true,
+                proportional: false,
+            },
+            palette_name: "test".to_string(),
+        }
+    }
+
+    fn even() -> PaletteSource {
+        let mut gradient = Gradient::from_colors(&[(0, 0, 0), (255, 128, 0), (255, 255, 255)]);
+        gradient.interpolation = Interpolation::Oklab;
+        PaletteSource::Embedded(gradient)
+    }
+
+    fn positioned() -> PaletteSource {
+        PaletteSource::Embedded(Gradient::new(
+            &[(0.0, (0, 0, 64)), (0.1, (255, 255, 255)), (0.75, (200, 0, 0)), (1.0, (0, 0, 0))],
+            Interpolation::Spline
+        ).unwrap())
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        for palette in [even(), positioned(), PaletteSource::File("palettes/fire.gpl".into())] {
+            let location = location(palette);
+            assert_eq!(Location::parse(&location.to_toml()), Ok(location));
+        }
+    }
+
+    #[test]
+    fn toml_round_trip_defaults() {
+        let location = Location {
+            bailout: None,
+            stripe_density: None,
+            layers: Vec::new(),
+            shading: None,
+            palette_map: PaletteMap::default(),
+            ..location(even())
+        };
+        let text = location.to_toml();
+
+        assert!(!text.contains("[shading]") && !text.contains("layers") && !text.contains("offset"));
+        assert!(!text.contains("bailout") && !text.contains("stripe_density"));
+        assert_eq!(Location::parse(&text), Ok(location));
+    }
+
+    #[test]
+    fn coordinates_keep_their_digits() {
+        let location = location(even());
+        let text = location.to_toml();
+
+        assert!(text.contains("\"-0.743643887037158704752191506114774\""));
+        assert!(text.contains("\"1.5e-20\""));
+
+        // Moving the view drops the digits that no longer apply
+        let moved = Location { sector: location.sector.zoom(2.0), ..location.clone() };
+        let (re, _, radius) = moved.center_radius();
+        assert_eq!(re, "-0.743643887037158704752191506114774");
+        assert_eq!(radius, moved.sector.center_radius().2);
+    }
+
+    #[test]
+    fn png_text_round_trip() {
+        for palette in [even(), positioned()] {
+            let location = location(palette);
+            let info = PngInfo { w: 640, h: 480, text: location.to_png_text() };
+            let (parsed, warnings) = Location::from_png_text(&info).unwrap();
+
+            assert!(warnings.is_empty());
+            assert_eq!(parsed, location);
+        }
+    }
+}
@@ -1,6 +1,7 @@
-mod mandelbrot;
+mod font;
 mod mainapp;
 mod mathutils;
+mod palette_editor;
 
 use color_eyre::eyre::Result;
 use salty_broth::sdl_app::AppBuilder;
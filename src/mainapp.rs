@@ -1,5 +1,4 @@
 use color_eyre::Result;
-use gimp_palette::{Palette, NewPaletteError};
 use rfd::AsyncFileDialog;
 use salty_broth::{
     dispatch_handlers,
@@ -10,27 +9,68 @@ use sdl_dispatch::SdlPumpTask;
 use sdl2::{
     render::{ Canvas, Texture, TextureCreator },
     video::{ Window, WindowContext },
-    event::Event,
-    keyboard::Keycode,
+    event::{ Event, WindowEvent },
+    keyboard::{ Keycode, Mod },
     mouse::MouseButton,
     pixels::{ Color, PixelFormatEnum },
     rect::{ Rect, Point },
+    render::BlendMode,
 };
 use std::{
     mem,
     path::PathBuf,
     ptr::null_mut,
+    time::{ Instant, SystemTime },
 };
 use tokio::{
+    sync::watch,
     time::Duration,
     task::JoinHandle,
 };
 use tokio_util::sync::CancellationToken;
-use crate::mandelbrot::{self, MandelbrotSetWithHistogram};
+use mandelbrot_rs::animation;
+use mandelbrot_rs::bookmarks::{ self, Bookmark, Library, Thumbnail };
+use mandelbrot_rs::colorizer::{ self, Layer, Mapping, PaletteMap, Shading };
+use mandelbrot_rs::dither::Dither;
+use mandelbrot_rs::export::{ self, ImageWriter };
+use mandelbrot_rs::gradient::{ Gradient, Interpolation };
+use mandelbrot_rs::location::{ self, Location, PaletteSource };
+use mandelbrot_rs::mandelbrot::{self, Coloring, ComputeOptions, Formula, MandelbrotSetWithHistogram, Progress};
+use mandelbrot_rs::palette;
+use mandelbrot_rs::poster;
+use mandelbrot_rs::raw;
+use crate::font;
 use crate::mathutils;
+use crate::palette_editor::{ self, PaletteEditor };
 
 type Real = f64;
 
+/// Angle the view turns by at each Q/E key press.
+const ROTATION_STEP: f64 = std::f64::consts::PI / 36.0;
+
+/// Shift of the palette at each [/] key press, 1 being a whole palette.
+const PALETTE_OFFSET_STEP: f32 = 1.0 / 32.0;
+
+/// Time between two steps of color cycling.
+const CYCLE_TICK: Duration = Duration::from_millis(40);
+/// Colors a whole color cycle goes through.
+const CYCLE_COLORS: usize = 256;
+/// Fastest color cycling, in colors per step.
+const CYCLE_MAX_SPEED: i32 = 8;
+
+/// Time between two updates of the progress bar, and its height.
+const PROGRESS_TICK: Duration = Duration::from_millis(100);
+const PROGRESS_HEIGHT: u32 = 3;
+
+/// Screen pixels per font pixel of the HUD, and its distance to the edges.
+const HUD_SCALE: u32 = 2;
+const HUD_MARGIN: i32 = 8;
+
+/// Size of bookmark thumbnails, and of their cells in the bookmarks panel.
+const THUMBNAIL_WIDTH: usize = 128;
+const THUMBNAIL_CELL: (u32, u32) = (128, 96);
+const THUMBNAIL_MARGIN: u32 = 8;
+
 /// Represents the handler for SDL events, keeps track of redraw
 /// processes.
 pub struct MainApp {
@@ -42,8 +82,42 @@ pub struct MainApp {
     w: u32, h: u32,
     selection_center: Option<Point>,
     selection: Option<Rect>,
-    palette: Vec<(u8, u8, u8)>,
+    pan_start: Option<Point>,
+    prompt: Option<Prompt>,
+    window_title: String,
+    bookmarks: Library<Real>,
+    bookmark_textures: Vec<Texture>,
+    /// Index of the highlighted bookmark while the panel is open.
+    bookmark_panel: Option<usize>,
+    /// Cancels the poster being exported, if any.
+    poster_task: Option<CancellationToken>,
+    color_cycle: Option<ColorCycle>,
+    palette_editor: Option<PaletteEditor>,
+    palette: Gradient,
+    palette_name: String,
+    palette_map: PaletteMap,
+    /// How the view is rounded to the 8 bits of the texture.
+    dither: Dither,
+    /// Whether the location and computation status are shown.
+    hud: bool,
+    /// Position of the mouse in the window, while it is over it.
+    mouse: Option<Point>,
+    /// When the computation in progress started.
+    render_start: Option<Instant>,
+    render_progress: Option<RenderProgress>,
+    /// How long the last complete computation took.
+    render_time: Option<Duration>,
     sector: mandelbrot::Sector<Real>,
+    /// The center and radius as typed or loaded, see `Location::coordinates`.
+    coordinates: Option<(String, String, String)>,
+    formula: Formula,
+    maxiter: usize,
+    /// Settings of the loaded location, see `Location::bailout`.
+    bailout: Option<f64>,
+    stripe_density: Option<f64>,
+    coloring: Coloring,
+    layers: Vec<Layer>,
+    shading: Option<Shading>,
     mandelbrot_set: mandelbrot::MandelbrotSetWithHistogram,
 }
 
@@ -55,7 +129,7 @@ impl TryFrom<Canvas<Window>> for MainApp {
         let texture_creator = canvas.texture_creator();
         let texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, w, h)
             .map_err(|e| e.to_string())?;
-        let scale: Real = 4.0 / Real::from(h);
+        let window_title = canvas.window().title().to_string();
         Ok(Self {
             canvas,
             texture_creator,
@@ -65,13 +139,36 @@ impl TryFrom<Canvas<Window>> for MainApp {
             w, h,
             selection_center: None,
             selection: None,
-            palette: vec![(0, 0, 0), (255,255, 255)],
-            sector: mandelbrot::Sector::new(
-                - Real::from(w / 2) * scale,
-                - Real::from(h / 2) * scale,
-                scale,
-                w as usize, h as usize
-            ),
+            pan_start: None,
+            prompt: None,
+            window_title,
+            bookmarks: Library {
+                path: bookmarks::default_library_path(),
+                bookmarks: Vec::new(),
+            },
+            bookmark_textures: Vec::new(),
+            bookmark_panel: None,
+            poster_task: None,
+            color_cycle: None,
+            palette_editor: None,
+            palette: Gradient::default(),
+            palette_name: "black and white".to_string(),
+            palette_map: PaletteMap::default(),
+            dither: Dither::default(),
+            hud: false,
+            mouse: None,
+            render_start: None,
+            render_progress: None,
+            render_time: None,
+            sector: mandelbrot::Sector::new((0.0, 0.0), 2.0, w as usize, h as usize),
+            coordinates: None,
+            formula: Formula::default(),
+            maxiter: 20000,
+            bailout: None,
+            stripe_density: None,
+            coloring: Coloring::default(),
+            layers: Vec::new(),
+            shading: None,
             mandelbrot_set: Default::default(),
         })
     }
@@ -85,13 +182,23 @@ impl Drop for MainApp {
                 self.texture_creator
                     .raw_create_texture(null_mut())
             ).destroy();
+
+            for texture in self.bookmark_textures.drain(..) {
+                texture.destroy();
+            }
         }
     }
 }
 
 impl sdl_app::App for MainApp {
-    /// Application start does simply clear the main window.
+    /// Application start clears the main window and opens
+    /// the default bookmarks library.
     fn start(&mut self) {
+        match Library::open(&self.bookmarks.path) {
+            Ok(library) => self.set_bookmarks(library),
+            Err(err) => println!("{}", err),
+        }
+
         _ = self.texture.with_lock(None, |buf, _| -> Result<(), String> {
             for b in buf {
                 *b = 0;
@@ -130,6 +237,18 @@ impl sdl_app::App for MainApp {
     }
 
     fn sdl_event(&mut self, event: Event) {
+        if self.prompt.is_some() && self.prompt_event(&event) {
+            return;
+        }
+
+        if self.bookmark_panel.is_some() && self.bookmark_panel_event(&event) {
+            return;
+        }
+
+        if self.palette_editor.is_some() && self.palette_editor_event(&event) {
+            return;
+        }
+
         match event {
             Event::KeyUp { keycode: Some(keycode), .. } => {
                 match keycode {
@@ -138,30 +257,273 @@ impl sdl_app::App for MainApp {
                             if let Some(palettefile) = 
                                 choose_palette().await {
                                 let palette_load_result =
-                                    gimp_palette::Palette::read_from_file(palettefile.clone())
-                                    .map(|p| p
-                                        .get_colors()
-                                        .iter()
-                                        .map(|c| (c.r, c.g, c.b))
-                                        .collect::<Vec<(u8, u8, u8)>>()
-                                    )
-                                    .map_err(|_e| format!(
-                                        "Error loading palette from {}",
-                                        palettefile.to_string_lossy()
-                                    ));
+                                    palette::read_palette(&palettefile);
+                                let palette_name = palettefile
+                                    .file_stem()
+                                    .unwrap_or_default()
+                                    .to_string_lossy()
+                                    .to_string();
                                 sdl_dispatch::send::<PaletteChanged>(
-                                    PaletteChanged { palette_load_result }
+                                    PaletteChanged { palette_load_result, palette_name }
                                 );
                             }
                         });
                     },
+                    Keycode::K => {
+                        let gradient = self.palette.clone();
+                        let name = self.palette_name.clone();
+                        tokio::spawn(async move {
+                            if let Some(palettefile) = save_palette(&name).await {
+                                if let Some(err) = palette::write_palette(
+                                    &palettefile, &gradient, &name
+                                ).err() {
+                                    println!("{}", err);
+                                }
+                            }
+                        });
+                    },
+                    Keycode::S => {
+                        let location = self.location();
+                        tokio::spawn(async move {
+                            if let Some(locationfile) = save_location().await {
+                                if let Some(err) = location.save(&locationfile).err() {
+                                    println!("{}", err);
+                                }
+                            }
+                        });
+                    },
+                    Keycode::X => {
+                        let image = self.image(&self.palette);
+                        let (w, h) = self.mandelbrot_set.size();
+                        let text = self.location().to_png_text();
+                        tokio::spawn(async move {
+                            if let Some(imagefile) = save_screenshot().await {
+                                if let Some(err) = export::write_image(
+                                    &imagefile, &image, w, h, &text
+                                ).err() {
+                                    println!("{}", err);
+                                }
+                            }
+                        });
+                    },
+                    Keycode::D => {
+                        let mandelbrot_set = self.mandelbrot_set.clone();
+                        tokio::spawn(async move {
+                            if let Some(datafile) = save_data().await {
+                                if let Some(err) = raw::write_raw(&datafile, &mandelbrot_set).err() {
+                                    println!("{}", err);
+                                }
+                            }
+                        });
+                    },
+                    Keycode::A => {
+                        self.start_prompt(PromptAction::Animation);
+                    },
+                    Keycode::R => {
+                        match self.poster_task.take() {
+                            Some(ct) => ct.cancel(),
+                            None => self.start_prompt(PromptAction::Poster),
+                        }
+                    },
+                    Keycode::O => {
+                        let (w, h) = (self.w as usize, self.h as usize);
+                        tokio::spawn(async move {
+                            if let Some(locationfile) = open_location().await {
+                                match Location::open(&locationfile, w, h)
+                                    .and_then(|(location, warnings)| {
+                                        for warning in warnings {
+                                            println!("{}", warning);
+                                        }
+                                        let palette = location.load_palette()?;
+                                        Ok(LocationLoaded { location, palette })
+                                    }) {
+                                    Ok(msg) => sdl_dispatch::send::<LocationLoaded>(msg),
+                                    Err(err) => println!("{}", err),
+                                }
+                            }
+                        });
+                    },
+                    Keycode::G => {
+                        self.start_prompt(PromptAction::GoTo);
+                    },
+                    Keycode::B => {
+                        self.start_prompt(PromptAction::Bookmark);
+                    },
+                    Keycode::Tab => {
+                        if self.bookmarks.bookmarks.is_empty() {
+                            println!("No bookmarks in {}", self.bookmarks.path.to_string_lossy());
+                        } else {
+                            self.bookmark_panel = Some(0);
+                            self.show_bookmark_panel();
+                        }
+                    },
+                    Keycode::L => {
+                        tokio::spawn(async {
+                            if let Some(libraryfile) = choose_library().await {
+                                match Library::open(&libraryfile) {
+                                    Ok(library) => sdl_dispatch::send::<LibraryOpened>(
+                                        LibraryOpened { library }
+                                    ),
+                                    Err(err) => println!("{}", err),
+                                }
+                            }
+                        });
+                    },
+                    Keycode::C => {
+                        let (re, im, radius) = self.location().center_radius();
+                        if let Some(err) = self.canvas
+                            .window()
+                            .subsystem()
+                            .clipboard()
+                            .set_clipboard_text(&format!("{} {} {}", re, im, radius))
+                            .err() {
+                            println!("{}", err);
+                        }
+                    },
+                    Keycode::U => {
+                        self.palette_editor = match self.palette_editor {
+                            Some(_) => None,
+                            None => Some(PaletteEditor::new(&self.palette)),
+                        };
+                        _ = self.render();
+                    },
+                    Keycode::I => {
+                        let next = match self.palette.interpolation {
+                            Interpolation::Rgb => Interpolation::LinearRgb,
+                            Interpolation::LinearRgb => Interpolation::Hsv,
+                            Interpolation::Hsv => Interpolation::Oklab,
+                            Interpolation::Oklab => Interpolation::Spline,
+                            Interpolation::Spline => Interpolation::Rgb,
+                        };
+                        self.palette.interpolation = next;
+                        println!("Interpolation: {}", next.name());
+                        _ = self.update_texture();
+                        _ = self.render();
+                    },
+                    Keycode::H => {
+                        let options = self.location().compute_options();
+                        self.shading = match self.shading {
+                            Some(_) => None,
+                            None => Some(Shading::default()),
+                        };
+                        self.recolor(options);
+                    },
+                    Keycode::J => {
+                        if let Some(shading) = &mut self.shading {
+                            shading.azimuth = (shading.azimuth + 45.0).rem_euclid(360.0);
+                            println!("Light azimuth: {}", shading.azimuth);
+                            _ = self.update_texture();
+                            _ = self.render();
+                        }
+                    },
+                    Keycode::M => {
+                        let index = Coloring::ALL
+                            .iter()
+                            .position(|&c| c == self.coloring)
+                            .unwrap_or(0);
+                        let options = self.location().compute_options();
+                        self.coloring = Coloring::ALL[(index + 1) % Coloring::ALL.len()];
+                        println!("Coloring: {}", self.coloring.name());
+                        self.recolor(options);
+                    },
+                    Keycode::LeftBracket | Keycode::RightBracket => {
+                        let step = if keycode == Keycode::LeftBracket {
+                            -PALETTE_OFFSET_STEP
+                        } else {
+                            PALETTE_OFFSET_STEP
+                        };
+                        let offset = (self.palette_map.offset + step).rem_euclid(1.0);
+                        self.remap(PaletteMap { offset, ..self.palette_map });
+                    },
+                    Keycode::Comma | Keycode::Period => {
+                        let step = if keycode == Keycode::Comma { -1.0 } else { 1.0 };
+                        let repeat = (self.palette_map.repeat + step).max(1.0);
+                        self.remap(PaletteMap { repeat, ..self.palette_map });
+                    },
+                    Keycode::N => {
+                        let density = match self.palette_map.density {
+                            Mapping::Linear => Mapping::Log,
+                            Mapping::Log => Mapping::Sqrt,
+                            Mapping::Sqrt => Mapping::Linear,
+                        };
+                        self.remap(PaletteMap { density, ..self.palette_map });
+                    },
+                    Keycode::F => {
+                        let reverse = !self.palette_map.reverse;
+                        self.remap(PaletteMap { reverse, ..self.palette_map });
+                    },
+                    Keycode::T => {
+                        let proportional = !self.palette_map.proportional;
+                        self.remap(PaletteMap { proportional, ..self.palette_map });
+                    },
+                    Keycode::V => {
+                        self.hud = !self.hud;
+                        _ = self.render();
+                    },
+                    Keycode::Z => {
+                        self.dither = match self.dither {
+                            Dither::None => Dither::Ordered,
+                            Dither::Ordered => Dither::BlueNoise,
+                            Dither::BlueNoise => Dither::None,
+                        };
+                        println!("Dithering: {}", self.dither.name());
+                        _ = self.update_texture();
+                        _ = self.render();
+                    },
+                    Keycode::Space => {
+                        self.color_cycle = match self.color_cycle.take() {
+                            Some(_) => None,
+                            None => Some(ColorCycle {
+                                offset: 0,
+                                speed: 1,
+                                _ticker: Ticker::looping(CYCLE_TICK, || {
+                                    sdl_dispatch::send::<CycleTick>(CycleTick{});
+                                }),
+                            }),
+                        };
+                        // Stopping restores the palette
+                        _ = self.update_texture();
+                        _ = self.render();
+                    },
+                    Keycode::Equals | Keycode::Plus | Keycode::KpPlus
+                    | Keycode::Minus | Keycode::KpMinus => {
+                        if let Some(cycle) = &mut self.color_cycle {
+                            let faster = matches!(
+                                keycode,
+                                Keycode::Equals | Keycode::Plus | Keycode::KpPlus
+                            );
+                            cycle.speed = (cycle.speed + if faster { 1 } else { -1 })
+                                .clamp(-CYCLE_MAX_SPEED, CYCLE_MAX_SPEED);
+                            println!("Color cycling speed: {}", cycle.speed);
+                        }
+                    },
+                    Keycode::Q => {
+                        self.sector = self.sector.rotate(ROTATION_STEP);
+                        sdl_dispatch::send::<Redraw>(Redraw{});
+                    },
+                    Keycode::E => {
+                        self.sector = self.sector.rotate(-ROTATION_STEP);
+                        sdl_dispatch::send::<Redraw>(Redraw{});
+                    },
                     _ => {}
                 }
             },
             Event::MouseButtonDown{ mouse_btn: MouseButton::Left, x, y, ..} => {
                 self.selection_center = Some(Point::new(x, y))
             },
+            Event::MouseButtonDown{ mouse_btn: MouseButton::Right, x, y, ..} => {
+                self.pan_start = Some(Point::new(x, y))
+            },
+            Event::MouseButtonUp{ mouse_btn: MouseButton::Right, x, y, ..} => {
+                if let Some(start) = self.pan_start.take() {
+                    // Dragging moves the image, so the view moves
+                    // the opposite way; y is inverted on the complex plane.
+                    self.sector = self.sector.pan(start.x - x, y - start.y);
+                    sdl_dispatch::send::<Redraw>(Redraw{});
+                }
+            },
             Event::MouseMotion{x, y, ..} => {
+                self.mouse = Some(Point::new(x, y));
                 if let Some(center) = self.selection_center {
                     self.selection = Some(mathutils::selection_from_center_with_ratio(
                         center,
@@ -171,6 +533,14 @@ impl sdl_app::App for MainApp {
                     if let Some(err) = self.render().err() {
                         println!("{}", err);
                     }
+                } else if self.hud {
+                    _ = self.render();
+                }
+            },
+            Event::Window { win_event: WindowEvent::Leave, .. } => {
+                self.mouse = None;
+                if self.hud {
+                    _ = self.render();
                 }
             },
             Event::MouseButtonUp{mouse_btn: MouseButton::Left, x, y, ..} => {
@@ -185,7 +555,12 @@ impl sdl_app::App for MainApp {
                         Point::new(x, self.h as i32 - y),
                         self.w as f32 / self.h as f32
                     );
-                    self.sector = self.sector.zoom_to_selection(selection);
+                    self.sector = self.sector.zoom_to_selection(
+                        selection.x(),
+                        selection.y(),
+                        selection.width(),
+                        selection.height()
+                    );
                     sdl_dispatch::send::<Redraw>(Redraw{});
                 }
 
@@ -200,14 +575,80 @@ impl sdl_app::App for MainApp {
     }
 }
 
+/// What to do with the text typed in a `Prompt`.
+enum PromptAction {
+    /// Moves the view to a location written as "re im radius".
+    GoTo,
+    /// Adds the current view to the bookmarks with the typed name.
+    Bookmark,
+    /// Exports the current view as a poster of the typed size.
+    Poster,
+    /// Records a palette cycling animation of the current view,
+    /// typed as "frames delay".
+    Animation,
+}
+
+impl PromptAction {
+    fn label(&self) -> &'static str {
+        match self {
+            PromptAction::GoTo => "Go to (re im radius)",
+            PromptAction::Bookmark => "Bookmark name",
+            PromptAction::Poster => "Poster size (WxH)",
+            PromptAction::Animation => "Palette cycle (frames delay_ms)",
+        }
+    }
+}
+
+/// Palette rotation shown while the set stays the same, only
+/// recoloring it at every tick.
+struct ColorCycle {
+    /// Colors the palette has been rotated by, out of `CYCLE_COLORS`.
+    offset: usize,
+    /// Colors added to `offset` at every tick, negative going backwards
+    /// and zero pausing.
+    speed: i32,
+    _ticker: Ticker,
+}
+
+/// Progress of the computation in progress, sampled at every tick
+/// rather than redrawing the window for every row of pixels.
+struct RenderProgress {
+    receiver: watch::Receiver<Progress>,
+    _ticker: Ticker,
+}
+
+/// A line of text being typed by the user. As we have no widgets
+/// the text is shown in the window title.
+struct Prompt {
+    action: PromptAction,
+    text: String,
+}
+
 struct ResizeTexture {}
 struct Redraw {}
 struct MandelbrotReady {
     mandelbrotset: MandelbrotSetWithHistogram,
 }
 struct PaletteChanged {
-    palette_load_result: Result<Vec<(u8, u8, u8)>, String>,
+    palette_load_result: Result<Gradient, String>,
+    palette_name: String,
+}
+struct LocationLoaded {
+    location: Location<Real>,
+    palette: Gradient,
+}
+struct LibraryOpened {
+    library: Library<Real>,
 }
+struct PosterProgress {
+    done: usize,
+    total: usize,
+}
+struct PosterDone {
+    result: Result<(), String>,
+}
+struct CycleTick {}
+struct ProgressTick {}
 
 dispatch_handlers! {
     MainApp ,
@@ -231,14 +672,25 @@ dispatch_handlers! {
         }
 
         let cancellation_token = CancellationToken::new();
-        
+        let (progress, receiver) = watch::channel(Progress::default());
+        self.render_start = Some(Instant::now());
+        self.render_progress = Some(RenderProgress {
+            receiver,
+            _ticker: Ticker::looping(PROGRESS_TICK, || {
+                sdl_dispatch::send::<ProgressTick>(ProgressTick{});
+            }),
+        });
+        _ = self.render();
+
         self.mandelbrot_task = Some((tokio::spawn({
             let sector = self.sector.clone();
+            let options = self.location().compute_options();
             let cancellation_token_clone = cancellation_token.clone();
             async move{
-                if let Some(mandelbrotset) = sector.compute(
-                    20000,
-                    cancellation_token_clone
+                if let Some(mandelbrotset) = sector.compute_with_progress(
+                    options,
+                    cancellation_token_clone,
+                    progress
                 ).await {
                     sdl_dispatch::spawn::<MandelbrotReady, Result<(), String>>(
                         MandelbrotReady { mandelbrotset }
@@ -266,6 +718,8 @@ dispatch_handlers! {
                 .input()
                 .mandelbrotset
                 .clone();
+            self.render_time = self.render_start.take().map(|start| start.elapsed());
+            self.render_progress = None;
             self.update_texture()?;
             self.render()?;
             Ok(())
@@ -275,26 +729,584 @@ dispatch_handlers! {
     }
 
     fn palette_changed(&mut self, msg: PaletteChanged) {
-        self.palette = msg.palette_load_result.unwrap();
+        self.palette = match msg.palette_load_result {
+            Ok(palette) => palette,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            },
+        };
+        self.palette_name = msg.palette_name;
+        self.reset_palette_editor();
         self.update_texture();
         self.render();
     }
+
+    fn location_loaded(&mut self, msg: LocationLoaded) {
+        self.apply_location(msg.location, msg.palette);
+    }
+
+    fn library_opened(&mut self, msg: LibraryOpened) {
+        self.set_bookmarks(msg.library);
+    }
+
+    fn poster_progress(&mut self, msg: PosterProgress) {
+        if self.poster_task.is_some() && self.prompt.is_none() && self.bookmark_panel.is_none() {
+            let title = format!(
+                "Exporting poster: tile {}/{}, R cancels",
+                msg.done,
+                msg.total
+            );
+            _ = self.canvas.window_mut().set_title(&title);
+        }
+    }
+
+    fn progress_tick(&mut self, _msg: ProgressTick) {
+        if self
+            .render_progress
+            .as_mut()
+            .is_some_and(|progress| progress.receiver.has_changed().unwrap_or(false))
+        {
+            _ = self.render();
+        }
+    }
+
+    fn cycle_tick(&mut self, _msg: CycleTick) {
+        let Some(cycle) = &mut self.color_cycle else {
+            return;
+        };
+
+        let offset = cycle.offset as i64 + cycle.speed as i64;
+        cycle.offset = offset.rem_euclid(CYCLE_COLORS as i64) as usize;
+        if cycle.speed != 0 {
+            _ = self.update_texture();
+            _ = self.render();
+        }
+    }
+
+    fn poster_done(&mut self, msg: PosterDone) {
+        self.poster_task = None;
+        if self.prompt.is_none() && self.bookmark_panel.is_none() {
+            _ = self.canvas.window_mut().set_title(&self.window_title);
+        }
+
+        if let Some(err) = msg.result.err() {
+            println!("{}", err);
+        }
+    }
 }
 
 impl MainApp {
+    fn location(&self) -> Location<Real> {
+        Location {
+            sector: self.sector.clone(),
+            coordinates: self.coordinates.clone(),
+            formula: self.formula,
+            maxiter: self.maxiter,
+            bailout: self.bailout,
+            stripe_density: self.stripe_density,
+            coloring: self.coloring,
+            layers: self.layers.clone(),
+            shading: self.shading,
+            palette: PaletteSource::Embedded(self.palette.clone()),
+            palette_map: self.palette_map,
+            palette_name: self.palette_name.clone(),
+        }
+    }
+
+    fn apply_location(&mut self, location: Location<Real>, palette: Gradient) {
+        self.sector = location.sector.with_size(self.w as usize, self.h as usize);
+        self.coordinates = location.coordinates;
+        self.formula = location.formula;
+        self.maxiter = location.maxiter;
+        self.bailout = location.bailout;
+        self.stripe_density = location.stripe_density;
+        self.coloring = location.coloring;
+        self.layers = location.layers;
+        self.shading = location.shading;
+        self.palette = palette;
+        self.palette_name = location.palette_name;
+        self.palette_map = location.palette_map;
+        self.reset_palette_editor();
+        sdl_dispatch::send::<Redraw>(Redraw{});
+    }
+
+    /// Makes an open palette editor edit the current palette.
+    fn reset_palette_editor(&mut self) {
+        if self.palette_editor.is_some() {
+            self.palette_editor = Some(PaletteEditor::new(&self.palette));
+        }
+    }
+
+    /// Feeds an event to the palette editor, recoloring the view as
+    /// the palette is edited. Returns `true` if the event was consumed.
+    fn palette_editor_event(&mut self, event: &Event) -> bool {
+        let Some(editor) = self.palette_editor.as_mut() else {
+            return false;
+        };
+
+        match editor.event(event, &self.palette, self.w, self.h) {
+            palette_editor::Response::Ignored => return false,
+            palette_editor::Response::Handled => {},
+            palette_editor::Response::Changed => {
+                match Gradient::new(editor.stops(), self.palette.interpolation) {
+                    Ok(gradient) => self.palette = gradient,
+                    Err(err) => println!("{}", err),
+                }
+                _ = self.update_texture();
+            },
+        }
+
+        _ = self.render();
+        true
+    }
+
+    fn start_prompt(&mut self, action: PromptAction) {
+        self.prompt = Some(Prompt {
+            action,
+            text: String::new(),
+        });
+        self.show_prompt();
+    }
+
+    fn show_prompt(&mut self) {
+        if let Some(prompt) = &self.prompt {
+            let title = format!("{}: {}_", prompt.action.label(), prompt.text);
+            _ = self.canvas.window_mut().set_title(&title);
+        }
+    }
+
+    /// Feeds an event to the active prompt, returns `true` if the
+    /// event was consumed. Return confirms, an empty prompt is
+    /// simply dismissed.
+    fn prompt_event(&mut self, event: &Event) -> bool {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return false;
+        };
+
+        match event {
+            Event::TextInput { text, .. } => {
+                prompt.text.push_str(text);
+            },
+            Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                prompt.text.pop();
+            },
+            Event::KeyDown { keycode: Some(Keycode::V), keymod, .. }
+                if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                if let Ok(text) = self.canvas.window().subsystem().clipboard().clipboard_text() {
+                    prompt.text.push_str(text.trim());
+                }
+            },
+            Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), .. } => {
+                let prompt = self.prompt.take().unwrap();
+                _ = self.canvas.window_mut().set_title(&self.window_title);
+                if let Some(err) = self.submit_prompt(prompt).err() {
+                    println!("{}", err);
+                }
+                return true;
+            },
+            Event::KeyDown { .. } | Event::KeyUp { .. } => {},
+            _ => return false,
+        }
+
+        self.show_prompt();
+        true
+    }
+
+    fn submit_prompt(&mut self, prompt: Prompt) -> Result<(), String> {
+        if prompt.text.trim().is_empty() {
+            return Ok(());
+        }
+
+        match prompt.action {
+            PromptAction::GoTo => {
+                let fields: Vec<&str> = prompt.text
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|f| !f.is_empty())
+                    .collect();
+                let [re, im, radius] = fields[..] else {
+                    return Err(format!("Expected \"re im radius\", got \"{}\"", prompt.text));
+                };
+                self.sector = mandelbrot::Sector::from_center_radius(
+                    re, im, radius,
+                    self.w as usize, self.h as usize
+                )?.rotate(self.sector.rotation());
+                self.coordinates = Some((re.to_string(), im.to_string(), radius.to_string()));
+                sdl_dispatch::send::<Redraw>(Redraw{});
+            },
+            PromptAction::Bookmark => {
+                let (w, h) = self.mandelbrot_set.size();
+                let image = self.image(&self.palette);
+                if image.is_empty() {
+                    return Err("Nothing to bookmark yet".to_string());
+                }
+
+                let bookmark = Bookmark {
+                    name: prompt.text.trim().to_string(),
+                    timestamp: SystemTime::now(),
+                    location: self.location(),
+                    thumbnail: Thumbnail::from_image(&image, w, h, THUMBNAIL_WIDTH),
+                };
+                let texture = self.thumbnail_texture(&bookmark.thumbnail)?;
+                self.bookmarks.bookmarks.push(bookmark);
+                self.bookmark_textures.push(texture);
+                self.bookmarks.save()?;
+            },
+            PromptAction::Animation => {
+                let (frames, delay_ms) = prompt.text
+                    .split_once(|c: char| c.is_whitespace() || c == ',')
+                    .and_then(|(f, d)| Some((f.trim().parse().ok()?, d.trim().parse().ok()?)))
+                    .filter(|&(frames, _): &(usize, u16)| frames > 0)
+                    .ok_or_else(|| format!("Expected \"frames delay\", got \"{}\"", prompt.text))?;
+                let mandelbrot_set = self.mandelbrot_set.clone();
+                let palette = self.palette.clone();
+                let (coloring, layers, shading) = (self.coloring, self.layers.clone(), self.shading);
+                let palette_map = self.palette_map;
+                let text = self.location().to_png_text();
+
+                tokio::spawn(async move {
+                    if let Some(animationfile) = save_animation().await {
+                        if let Some(err) = animation::write_palette_cycle(
+                            &animationfile,
+                            &mandelbrot_set,
+                            &palette,
+                            |cycled| colorizer::layered(coloring, &layers, shading, palette_map, cycled),
+                            frames,
+                            delay_ms,
+                            &text
+                        ).err() {
+                            println!("{}", err);
+                        }
+                    }
+                });
+            },
+            PromptAction::Poster => {
+                let (w, h) = prompt.text
+                    .trim()
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+                    .filter(|&(w, h): &(usize, usize)| w > 0 && h > 0)
+                    .ok_or_else(|| format!("Expected \"WxH\", got \"{}\"", prompt.text))?;
+                let sector = self.sector.with_size(w, h);
+                let options = self.location().compute_options();
+                let mut colorizer = colorizer::layered(
+                    self.coloring, &self.layers, self.shading, self.palette_map, &self.palette
+                );
+                let text = Location { sector: sector.clone(), ..self.location() }.to_png_text();
+                let dither = self.dither;
+                let ct = CancellationToken::new();
+                self.poster_task = Some(ct.clone());
+
+                tokio::spawn(async move {
+                    let Some(imagefile) = save_screenshot().await else {
+                        sdl_dispatch::send::<PosterDone>(PosterDone { result: Ok(()) });
+                        return;
+                    };
+
+                    let result = match ImageWriter::create(&imagefile, w, h, &text) {
+                        Ok(writer) => {
+                            let result = poster::export_poster(
+                                &sector, options, (colorizer.as_mut(), dither), poster::TILE_SIZE, writer, ct,
+                                |done, total| sdl_dispatch::send::<PosterProgress>(
+                                    PosterProgress { done, total }
+                                )
+                            ).await;
+                            // Don't leave half written images around
+                            if result.is_err() {
+                                _ = std::fs::remove_file(&imagefile);
+                            }
+                            result
+                        },
+                        Err(err) => Err(err),
+                    };
+                    sdl_dispatch::send::<PosterDone>(PosterDone { result });
+                });
+            },
+        }
+
+        Ok(())
+    }
+
+    fn thumbnail_texture(&self, thumbnail: &Thumbnail) -> Result<Texture, String> {
+        let mut texture = self.texture_creator
+            .create_texture_static(PixelFormatEnum::RGB24, thumbnail.w as u32, thumbnail.h as u32)
+            .map_err(|e| e.to_string())?;
+        texture
+            .update(None, &thumbnail.to_rgb24(), thumbnail.w * 3)
+            .map_err(|e| e.to_string())?;
+        Ok(texture)
+    }
+
+    /// Switches to another library, rebuilding thumbnail textures.
+    fn set_bookmarks(&mut self, library: Library<Real>) {
+        for texture in self.bookmark_textures.drain(..) {
+            unsafe {
+                texture.destroy();
+            }
+        }
+
+        self.bookmarks = library;
+        for bookmark in &self.bookmarks.bookmarks {
+            match self.thumbnail_texture(&bookmark.thumbnail) {
+                Ok(texture) => self.bookmark_textures.push(texture),
+                Err(err) => {
+                    println!("{}", err);
+                    break;
+                },
+            }
+        }
+
+        self.bookmarks.bookmarks.truncate(self.bookmark_textures.len());
+    }
+
+    /// Shows the highlighted bookmark in the window title,
+    /// or restores the title when the panel is closed.
+    fn show_bookmark_panel(&mut self) {
+        let title = match self.bookmark_panel {
+            Some(selected) => format!(
+                "Bookmarks: {} ({}/{})",
+                self.bookmarks.bookmarks[selected].name,
+                selected + 1,
+                self.bookmarks.bookmarks.len()
+            ),
+            None => self.window_title.clone(),
+        };
+
+        _ = self.canvas.window_mut().set_title(&title);
+        if let Some(err) = self.render().err() {
+            println!("{}", err);
+        }
+    }
+
+    /// Handles keys while the bookmarks panel is open: arrows move
+    /// the highlight, Return jumps to the bookmark, Delete removes it
+    /// and Tab closes the panel. Returns `true` if the event was consumed.
+    fn bookmark_panel_event(&mut self, event: &Event) -> bool {
+        let Some(selected) = self.bookmark_panel else {
+            return false;
+        };
+        let count = self.bookmarks.bookmarks.len();
+        let columns = self.bookmark_columns() as usize;
+
+        self.bookmark_panel = match event {
+            Event::KeyDown { keycode: Some(Keycode::Left), .. } =>
+                Some(selected.saturating_sub(1)),
+            Event::KeyDown { keycode: Some(Keycode::Right), .. } =>
+                Some((selected + 1).min(count - 1)),
+            Event::KeyDown { keycode: Some(Keycode::Up), .. } =>
+                Some(selected.checked_sub(columns).unwrap_or(selected)),
+            Event::KeyDown { keycode: Some(Keycode::Down), .. } =>
+                Some(if selected + columns < count { selected + columns } else { selected }),
+            Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), .. } => {
+                let location = self.bookmarks.bookmarks[selected].location.clone();
+                match location.load_palette() {
+                    Ok(palette) => self.apply_location(location, palette),
+                    Err(err) => println!("{}", err),
+                }
+                None
+            },
+            Event::KeyDown { keycode: Some(Keycode::Delete), .. } => {
+                self.bookmarks.bookmarks.remove(selected);
+                unsafe {
+                    self.bookmark_textures.remove(selected).destroy();
+                }
+                if let Some(err) = self.bookmarks.save().err() {
+                    println!("{}", err);
+                }
+                (count > 1).then(|| selected.min(count - 2))
+            },
+            Event::KeyUp { keycode: Some(Keycode::Tab), .. } => None,
+            Event::KeyDown { .. } | Event::KeyUp { .. } | Event::TextInput { .. } =>
+                return true,
+            _ => return false,
+        };
+
+        self.show_bookmark_panel();
+        true
+    }
+
+    fn bookmark_columns(&self) -> u32 {
+        (self.w.saturating_sub(THUMBNAIL_MARGIN) / (THUMBNAIL_CELL.0 + THUMBNAIL_MARGIN)).max(1)
+    }
+
+    /// Draws the location, the iterations and the state of the
+    /// computation in the top left corner, with the point under the mouse.
+    fn render_hud(&mut self) -> Result<(), String> {
+        let (re, im, _) = self.sector.center_radius();
+        let radius = self.sector.radius();
+        // Enough digits to tell neighboring pixels apart
+        let pixel = 2.0 * radius / self.h.max(1) as Real;
+        let digits = ((-pixel.log10()).ceil().max(0.0) as usize + 1).min(17);
+
+        let mut lines = vec![
+            format!("Re {}", re),
+            format!("Im {}", im),
+            format!("Zoom 10^{:.2}", (2.0 / radius).log10()),
+            format!("{}, {} iterations", self.formula.name(), self.maxiter),
+        ];
+        let progress = self.render_progress.as_ref().map(|p| *p.receiver.borrow());
+        match (self.render_start, self.render_time) {
+            (Some(start), _) => lines.push(match progress.and_then(|p| Some((p, p.remaining()?))) {
+                Some((progress, remaining)) => format!(
+                    "Rendering, {:.0}%, {:.1} s left",
+                    progress.fraction() * 100.0,
+                    remaining.as_secs_f64()
+                ),
+                None => format!("Rendering, {:.1} s", start.elapsed().as_secs_f64()),
+            }),
+            (None, Some(time)) => lines.push(format!("Rendered in {:.2} s", time.as_secs_f64())),
+            (None, None) => {},
+        }
+        if let Some(mouse) = self.mouse {
+            // Window y goes down, the imaginary axis up, as for selections
+            let (re, im) = self.sector.pixel_to_complex(
+                mouse.x() as Real,
+                (self.h as i32 - mouse.y()) as Real
+            );
+            lines.push(format!("Mouse {:.*} {:.*}", digits, re, digits, im));
+        }
+
+        let line_height = ((font::GLYPH_SIZE.1 + font::SPACING.1) * HUD_SCALE) as i32;
+        let width = lines
+            .iter()
+            .map(|line| font::text_size(line, HUD_SCALE).0)
+            .max()
+            .unwrap_or(0);
+        let height = lines.len() as i32 * line_height - (font::SPACING.1 * HUD_SCALE) as i32;
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        self.canvas.fill_rect(Rect::new(
+            0,
+            0,
+            width + 2 * HUD_MARGIN as u32,
+            (height + 2 * HUD_MARGIN) as u32
+        ))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        for (i, line) in lines.iter().enumerate() {
+            font::draw_text(
+                &mut self.canvas,
+                line,
+                (HUD_MARGIN, HUD_MARGIN + i as i32 * line_height),
+                HUD_SCALE
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws the thumbnails in a grid over the current image, scrolling
+    /// by whole pages so that the highlighted one is visible.
+    fn render_bookmark_panel(&mut self, selected: usize) -> Result<(), String> {
+        let columns = self.bookmark_columns() as usize;
+        let rows = (self.h.saturating_sub(THUMBNAIL_MARGIN) / (THUMBNAIL_CELL.1 + THUMBNAIL_MARGIN)).max(1) as usize;
+        let first = selected / (columns * rows) * columns * rows;
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        self.canvas.fill_rect(None)?;
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        for (i, texture) in self.bookmark_textures
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(columns * rows) {
+            let cell = Rect::new(
+                (THUMBNAIL_MARGIN + ((i - first) % columns) as u32 * (THUMBNAIL_CELL.0 + THUMBNAIL_MARGIN)) as i32,
+                (THUMBNAIL_MARGIN + ((i - first) / columns) as u32 * (THUMBNAIL_CELL.1 + THUMBNAIL_MARGIN)) as i32,
+                THUMBNAIL_CELL.0,
+                THUMBNAIL_CELL.1
+            );
+            let q = texture.query();
+            let scale = f32::min(
+                cell.width() as f32 / q.width as f32,
+                cell.height() as f32 / q.height as f32
+            );
+            self.canvas.copy(
+                texture,
+                None,
+                Rect::from_center(
+                    cell.center(),
+                    (q.width as f32 * scale) as u32,
+                    (q.height as f32 * scale) as u32
+                )
+            )?;
+
+            if i == selected {
+                self.canvas.set_draw_color(Color::RGB(255, 255, 0));
+                self.canvas.draw_rect(Rect::new(cell.x() - 2, cell.y() - 2, cell.width() + 4, cell.height() + 4))?;
+                self.canvas.draw_rect(Rect::new(cell.x() - 3, cell.y() - 3, cell.width() + 6, cell.height() + 6))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Colors the view again after a change of coloring, or computes
+    /// it again if it was computed with other `options` than needed.
+    fn recolor(&mut self, options: ComputeOptions) {
+        if self.location().compute_options() != options {
+            sdl_dispatch::send::<Redraw>(Redraw{});
+        } else {
+            _ = self.update_texture();
+            _ = self.render();
+        }
+    }
+
+    /// Lays the palette out with `map` and colors the view again.
+    fn remap(&mut self, map: PaletteMap) {
+        self.palette_map = map;
+        println!("Palette map: {}", location::format_palette_map(map));
+        let histogram = std::iter::once(self.coloring)
+            .chain(self.layers.iter().map(|layer| layer.coloring))
+            .any(|coloring| coloring == Coloring::Histogram);
+        if !histogram {
+            println!("The palette map only applies to the histogram coloring");
+        }
+        _ = self.update_texture();
+        _ = self.render();
+    }
+
+    /// The current view colored with `gradient`.
+    fn image(&self, gradient: &Gradient) -> Vec<(u8, u8, u8)> {
+        colorizer::colorize_dithered(
+            &self.mandelbrot_set,
+            colorizer::layered(
+                self.coloring, &self.layers, self.shading, self.palette_map, gradient
+            ).as_mut(),
+            self.dither
+        )
+    }
+
     fn update_texture(&mut self) -> Result<(), String> {
-        let image = self
-            .mandelbrot_set
-            .get_image_from_palette(&self.palette);
+        // Nothing computed yet, or the window was resized since
+        let (w, h) = self.mandelbrot_set.size();
+        if self.mandelbrot_set.pixels().is_empty() || (w, h) != (self.w as usize, self.h as usize) {
+            return Ok(());
+        }
 
-        // Lock texture and copy data
+        let image = match &self.color_cycle {
+            Some(cycle) => {
+                let colors = animation::cycle_colors(&self.palette, CYCLE_COLORS);
+                self.image(&Gradient::from_colors(
+                    &animation::cycle(&colors, cycle.offset, CYCLE_COLORS)
+                ))
+            },
+            None => self.image(&self.palette),
+        };
+
+        // Lock texture and copy data, the set being bottom-up
+        let query = self.texture.query();
+        let (texture_w, texture_h) = (w.min(query.width as usize), h.min(query.height as usize));
         _ = self.texture.with_lock(None, |buf, pitch| -> Result<(), String> {
-            for (y, y_tex) in
-                (0..self.h as usize)
-                .zip((0..self.h as usize).rev()) {
-                for x in 0..self.w as usize {
+            for y_tex in 0..texture_h {
+                let y = h - 1 - y_tex;
+                for x in 0..texture_w {
                     let pixel_index = pitch * y_tex + x * 3;
-                    let mandelbrot_index = self.w as usize * y + x;
+                    let mandelbrot_index = w * y + x;
                     (
                         buf[pixel_index],
                         buf[pixel_index + 1],
@@ -331,6 +1343,27 @@ impl MainApp {
             self.canvas.set_draw_color(Color::RGB(255, 0, 0));
             self.canvas.draw_rect(rect)?;
         }
+
+        if let Some(editor) = &self.palette_editor {
+            editor.render(&mut self.canvas, &self.palette)?;
+        }
+
+        if self.hud {
+            self.render_hud()?;
+        }
+
+        if let Some(progress) = &mut self.render_progress {
+            let fraction = progress.receiver.borrow_and_update().fraction();
+            let width = (self.w as f64 * fraction) as u32;
+            if width > 0 {
+                self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+                self.canvas.fill_rect(Rect::new(0, 0, width, PROGRESS_HEIGHT))?;
+            }
+        }
+
+        if let Some(selected) = self.bookmark_panel {
+            self.render_bookmark_panel(selected)?;
+        }
         
         self.canvas.present();
         Ok(())
@@ -339,9 +1372,92 @@ impl MainApp {
 
 async fn choose_palette() -> Option<PathBuf> {
     AsyncFileDialog::new()
-        .add_filter("GIMP palettes", &["gpl"])
+        .add_filter("Palettes", &["gpl", "map", "ugr", "ggr", "json"])
+        .add_filter("All files", &["*"])
         .set_directory("~")
         .pick_file()
         .await
         .map(|x| x.path().to_owned())
 }
+
+async fn open_location() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("All locations", &["toml", "kfr", "xpf", "png"])
+        .add_filter("Mandelbrot locations", &["toml"])
+        .add_filter("Kalles Fraktaler locations", &["kfr"])
+        .add_filter("XaoS positions", &["xpf"])
+        .add_filter("PNG screenshots", &["png"])
+        .set_directory("~")
+        .pick_file()
+        .await
+        .map(|x| x.path().to_owned())
+}
+
+async fn save_location() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("Mandelbrot locations", &["toml"])
+        .add_filter("Kalles Fraktaler locations", &["kfr"])
+        .add_filter("XaoS positions", &["xpf"])
+        .set_directory("~")
+        .set_file_name("location.toml")
+        .save_file()
+        .await
+        .map(|x| x.path().to_owned())
+}
+
+async fn save_screenshot() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("PNG images", &["png"])
+        .add_filter("PPM images", &["ppm"])
+        .set_directory("~")
+        .set_file_name("mandelbrot.png")
+        .save_file()
+        .await
+        .map(|x| x.path().to_owned())
+}
+
+async fn save_animation() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("GIF animations", &["gif"])
+        .add_filter("Animated PNG images", &["png"])
+        .set_directory("~")
+        .set_file_name("mandelbrot.gif")
+        .save_file()
+        .await
+        .map(|x| x.path().to_owned())
+}
+
+async fn save_palette(name: &str) -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("GIMP palettes", &["gpl"])
+        .add_filter("Fractint maps", &["map"])
+        .add_filter("UltraFractal gradients", &["ugr"])
+        .add_filter("GIMP gradients", &["ggr"])
+        .add_filter("JSON gradients", &["json"])
+        .set_directory("~")
+        .set_file_name(&format!("{}.gpl", name))
+        .save_file()
+        .await
+        .map(|x| x.path().to_owned())
+}
+async fn save_data() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("NumPy arrays", &["npy"])
+        .add_filter("OpenEXR images", &["exr"])
+        .add_filter("16 bits TIFF images", &["tif", "tiff"])
+        .set_directory("~")
+        .set_file_name("mandelbrot.npy")
+        .save_file()
+        .await
+        .map(|x| x.path().to_owned())
+}
+
+async fn choose_library() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("Bookmark libraries", &["toml"])
+        .set_directory("~")
+        .set_file_name("bookmarks.toml")
+        .save_file()
+        .await
+        .map(|x| x.path().to_owned())
+}
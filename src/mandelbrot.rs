@@ -1,7 +1,15 @@
-use std::vec::Vec;
-use sdl2::rect::Rect;
-use tokio::task::JoinHandle;
+use std::{
+    cmp::Ordering,
+    time::{ Duration, Instant },
+    vec::Vec,
+};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+};
 use tokio_util::sync::CancellationToken;
+use crate::colorizer::{ self, Histogram, Smooth };
+use crate::gradient::Gradient;
 
 pub trait Arithmetic:
     'static +
@@ -13,6 +21,11 @@ pub trait Arithmetic:
     std::convert::From<f32> +
     std::convert::From<i32> +
     std::convert::From<u32> +
+    std::convert::From<f64> +
+    std::str::FromStr +
+    std::fmt::Display +
+    std::fmt::LowerExp +
+    std::convert::Into<f64> +
     std::marker::Copy +
     std::marker::Send
 {
@@ -29,6 +42,11 @@ impl <T:
     std::convert::From<f32> +
     std::convert::From<i32> +
     std::convert::From<u32> +
+    std::convert::From<f64> +
+    std::str::FromStr +
+    std::fmt::Display +
+    std::fmt::LowerExp +
+    std::convert::Into<f64> +
     std::marker::Copy +
     std::marker::Send
     > Arithmetic for T
@@ -36,95 +54,637 @@ impl <T:
 
 }
 
+/// A `w` by `h` pixels view on the complex plane.
+///
+/// The view is centered in `(re, im)`, `radius` is the distance
+/// from the center to the top and bottom edges, and the whole view
+/// is rotated counterclockwise by `rotation` radians around its center.
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct Sector<Real: Arithmetic> {
-    left: Real,
-    bottom: Real,
-    scale: Real,
+    re: Real,
+    im: Real,
+    radius: Real,
+    rotation: f64,
     w: usize,
     h: usize,
 }
 
+/// The function iterated for each point of the complex plane.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Formula {
+    /// The classic z -> z² + c.
+    #[default]
+    Mandelbrot,
+}
+
+impl Formula {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Formula::Mandelbrot => "mandelbrot",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "mandelbrot" => Ok(Formula::Mandelbrot),
+            _ => Err(format!("Unknown formula \"{}\"", name)),
+        }
+    }
+}
+
+/// Smallest bailout radius, beyond which all orbits escape.
+pub const DEFAULT_BAILOUT: f64 = 2.0;
+/// Bailout radius of the colorings needing one, see `Coloring::bailout`.
+pub const LARGE_BAILOUT: f64 = 1024.0;
+pub const DEFAULT_STRIPE_DENSITY: f64 = 5.0;
+
+/// How points are iterated, beyond the view itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComputeOptions {
+    pub maxiter: usize,
+    /// Points escape once `|z|` reaches it, at least `DEFAULT_BAILOUT`.
+    pub bailout: f64,
+    /// Number of stripes of the stripe average, which isn't computed
+    /// without it, see `Orbit::stripe`.
+    pub stripe_density: Option<f64>,
+    /// Whether to compute the triangle inequality average, see `Orbit::tia`.
+    pub tia: bool,
+    /// Whether to keep the last value of the orbit, see `Orbit::z`.
+    pub angle: bool,
+    /// Whether to compute normals, see `Orbit::normal`.
+    pub normal: bool,
+}
+
+impl ComputeOptions {
+    pub fn new(maxiter: usize) -> Self {
+        Self {
+            maxiter,
+            bailout: DEFAULT_BAILOUT,
+            stripe_density: None,
+            tia: false,
+            angle: false,
+            normal: false,
+        }
+    }
+
+    /// Options computing what all of `colorings` need.
+    pub fn for_colorings(maxiter: usize, colorings: &[Coloring]) -> Self {
+        Self {
+            maxiter,
+            bailout: colorings
+                .iter()
+                .map(Coloring::bailout)
+                .fold(DEFAULT_BAILOUT, f64::max),
+            stripe_density: colorings
+                .contains(&Coloring::Stripe)
+                .then_some(DEFAULT_STRIPE_DENSITY),
+            tia: colorings.contains(&Coloring::Tia),
+            angle: colorings.iter().any(|coloring| matches!(
+                coloring,
+                Coloring::Angle | Coloring::Binary | Coloring::Decomposition | Coloring::FieldLines
+            )),
+            normal: false,
+        }
+    }
+}
+
+/// How pixels are turned into colors, see the `colorizer` module.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Coloring {
+    /// Histogram equalization.
+    #[default]
+    Histogram,
+    /// Iteration count over the maximum.
+    Linear,
+    Log,
+    Sqrt,
+    /// Smooth iteration count, cycling through the palette.
+    Smooth,
+    /// Distance estimate.
+    Distance,
+    /// Argument of the last value of the orbit.
+    Angle,
+    /// Stripe average.
+    Stripe,
+    /// Triangle inequality average.
+    Tia,
+    /// Two colors, by the sign of the imaginary part of the last
+    /// value of the orbit.
+    Binary,
+    /// Colors by which of several angular sectors the last value
+    /// of the orbit is in.
+    Decomposition,
+    /// Lines of equal external angle.
+    FieldLines,
+}
+
+impl Coloring {
+    pub const ALL: [Coloring; 12] = [
+        Coloring::Histogram,
+        Coloring::Linear,
+        Coloring::Log,
+        Coloring::Sqrt,
+        Coloring::Smooth,
+        Coloring::Distance,
+        Coloring::Angle,
+        Coloring::Stripe,
+        Coloring::Tia,
+        Coloring::Binary,
+        Coloring::Decomposition,
+        Coloring::FieldLines,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Coloring::Histogram => "histogram",
+            Coloring::Linear => "linear",
+            Coloring::Log => "log",
+            Coloring::Sqrt => "sqrt",
+            Coloring::Smooth => "smooth",
+            Coloring::Distance => "distance",
+            Coloring::Angle => "angle",
+            Coloring::Stripe => "stripe",
+            Coloring::Tia => "tia",
+            Coloring::Binary => "binary",
+            Coloring::Decomposition => "decomposition",
+            Coloring::FieldLines => "field-lines",
+        }
+    }
+
+    /// The bailout radius this coloring needs: colorings by the
+    /// argument of the last value of the orbit only line up across
+    /// iteration counts when it is large, and averages over the orbit
+    /// are smoother.
+    pub fn bailout(&self) -> f64 {
+        match self {
+            Coloring::Stripe
+            | Coloring::Tia
+            | Coloring::Angle
+            | Coloring::Binary
+            | Coloring::Decomposition
+            | Coloring::FieldLines => LARGE_BAILOUT,
+            _ => DEFAULT_BAILOUT,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|coloring| coloring.name() == name)
+            .ok_or_else(|| format!("Unknown coloring \"{}\"", name))
+    }
+}
+
+/// What was found iterating a single point.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pixel {
+    /// The point didn't escape within the maximum number of iterations.
+    pub interior: bool,
+    pub iterations: usize,
+    /// Continuous iteration count `n + 1 - log2(ln |z|)`,
+    /// zero for interior points.
+    pub smooth: f32,
+    /// Estimated distance to the set in pixels, `2 |z| ln |z| / |dz|`,
+    /// zero for interior points.
+    pub distance: f32,
+}
+
+/// What only some colorings need from the orbit of a point, computed
+/// when asked for, see `ComputeOptions`, and zero otherwise. All of
+/// them are zero for interior points.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Orbit {
+    /// Last value of the orbit.
+    pub z: (f32, f32),
+    /// Stripe average, of `(1 + sin(density arg z)) / 2` over the
+    /// orbit, between 0 and 1.
+    pub stripe: f32,
+    /// Triangle inequality average, of where `|z|` lies between its
+    /// bounds `||z²| - |c||` and `|z²| + |c|` over the orbit, between
+    /// 0 and 1.
+    pub tia: f32,
+    /// Unit vector pointing away from the set, the direction of
+    /// `z / dz`, with y going up the image.
+    pub normal: (f32, f32),
+}
+
+/// The `Orbit` of every pixel, one plane per value that was computed.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Orbits {
+    z: Option<Vec<(f32, f32)>>,
+    stripe: Option<Vec<f32>>,
+    tia: Option<Vec<f32>>,
+    normal: Option<Vec<(f32, f32)>>,
+}
+
+impl Orbits {
+    fn new(options: ComputeOptions, len: usize) -> Self {
+        Self {
+            z: options.angle.then(|| vec![(0.0, 0.0); len]),
+            stripe: options.stripe_density.map(|_| vec![0.0; len]),
+            tia: options.tia.then(|| vec![0.0; len]),
+            normal: options.normal.then(|| vec![(0.0, 0.0); len]),
+        }
+    }
+
+    fn set(&mut self, index: usize, orbit: Orbit) {
+        if let Some(z) = &mut self.z {
+            z[index] = orbit.z;
+        }
+        if let Some(stripe) = &mut self.stripe {
+            stripe[index] = orbit.stripe;
+        }
+        if let Some(tia) = &mut self.tia {
+            tia[index] = orbit.tia;
+        }
+        if let Some(normal) = &mut self.normal {
+            normal[index] = orbit.normal;
+        }
+    }
+
+    fn get(&self, index: usize) -> Orbit {
+        Orbit {
+            z: self.z.as_ref().map(|z| z[index]).unwrap_or_default(),
+            stripe: self.stripe.as_ref().map(|stripe| stripe[index]).unwrap_or_default(),
+            tia: self.tia.as_ref().map(|tia| tia[index]).unwrap_or_default(),
+            normal: self.normal.as_ref().map(|normal| normal[index]).unwrap_or_default(),
+        }
+    }
+}
+
+/// How far a computation got, see `Sector::compute_with_progress`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Progress {
+    /// Pixels computed so far, and in the whole image.
+    pub pixels: usize,
+    pub total: usize,
+    /// Iterations performed for the pixels computed so far.
+    pub iterations: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Between 0 and 1.
+    pub fn fraction(&self) -> f64 {
+        if self.total > 0 { self.pixels as f64 / self.total as f64 } else { 0.0 }
+    }
+
+    /// Time left if the remaining pixels take as long as the ones
+    /// computed so far, `None` until there are some.
+    pub fn remaining(&self) -> Option<Duration> {
+        estimate_remaining(self.elapsed, self.pixels, self.total)
+    }
+}
+
+/// Time left to finish `total` steps of equal length when `done` of
+/// them took `elapsed`.
+pub fn estimate_remaining(elapsed: Duration, done: usize, total: usize) -> Option<Duration> {
+    (done > 0).then(|| elapsed.mul_f64(total.saturating_sub(done) as f64 / done as f64))
+}
+
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct MandelbrotSetWithHistogram {
-    set: Vec<(bool, usize)>,
+    set: Vec<Pixel>,
+    orbits: Orbits,
     hist: Vec<usize>,
+    /// Prefix sums of `hist`.
+    cumulative: Vec<usize>,
     maxiter: usize,
+    w: usize,
+    h: usize,
 }
 
 impl<Real: Arithmetic> Sector<Real> {
-    pub fn new(left: Real, bottom: Real, scale: Real, w: usize, h: usize) -> Self {
-        Self { left, bottom, scale, w, h }
+    pub fn new((re, im): (Real, Real), radius: Real, w: usize, h: usize) -> Self {
+        Self { re, im, radius, rotation: 0.0, w, h }
+    }
+
+    /// Parses a sector from the decimal representation of its center
+    /// and radius. Strings can be of any length, they are rounded
+    /// to the nearest value representable by `Real`.
+    pub fn from_center_radius(
+        re: &str,
+        im: &str,
+        radius: &str,
+        w: usize,
+        h: usize
+    ) -> Result<Self, String> {
+        let parse = |name: &str, value: &str| value
+            .trim()
+            .parse::<Real>()
+            .map_err(|_| format!("Invalid {}: \"{}\"", name, value));
+        let radius = parse("radius", radius)?;
+
+        if radius.partial_cmp(&0.0f32.into()) != Some(Ordering::Greater) {
+            return Err("Radius must be greater than zero".to_string());
+        }
+
+        Ok(Self::new((parse("real part", re)?, parse("imaginary part", im)?), radius, w, h))
+    }
+
+    /// Returns the decimal representation of center and radius,
+    /// with as many digits as needed to parse them back exactly.
+    pub fn center_radius(&self) -> (String, String, String) {
+        (self.re.to_string(), self.im.to_string(), format!("{:e}", self.radius))
+    }
+
+    pub fn radius(&self) -> Real {
+        self.radius
+    }
+
+    pub fn rotation(&self) -> f64 {
+        self.rotation
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.w, self.h)
+    }
+
+    /// Changes the size in pixels, keeping center and radius.
+    pub fn with_size(&self, w: usize, h: usize) -> Self {
+        Self { w, h, ..self.clone() }
+    }
+
+    /// The part of the view covering `w` by `h` pixels from `(x, y)`,
+    /// with `y` growing upwards. Pixels of the tile map to the same
+    /// points as in the whole view.
+    pub fn tile(&self, x: usize, y: usize, w: usize, h: usize) -> Self {
+        let half = |size: usize| Real::from(size as u32) / 2.0f32.into();
+        let (re, im) = self.pixel_to_complex(
+            Real::from(x as u32) + half(w),
+            Real::from(y as u32) + half(h)
+        );
+
+        Self {
+            re,
+            im,
+            radius: self.scale() * half(h),
+            rotation: self.rotation,
+            w,
+            h,
+        }
+    }
+
+    /// Width of a pixel on the complex plane.
+    fn scale(&self) -> Real {
+        self.radius * 2.0f32.into() / Real::from(self.h as u32)
+    }
+
+    /// Maps a pixel, with `y` growing upwards, to its point
+    /// on the complex plane.
+    pub fn pixel_to_complex(&self, x: Real, y: Real) -> (Real, Real) {
+        let scale = self.scale();
+        let dx = (x - Real::from(self.w as u32) / 2.0f32.into()) * scale;
+        let dy = (y - Real::from(self.h as u32) / 2.0f32.into()) * scale;
+        let (dx, dy) = self.rotate_vector(dx, dy);
+
+        (self.re + dx, self.im + dy)
+    }
+
+    fn rotate_vector(&self, dx: Real, dy: Real) -> (Real, Real) {
+        if self.rotation == 0.0 {
+            return (dx, dy);
+        }
+
+        let (sin, cos) = self.rotation.sin_cos();
+        let (sin, cos) = (Real::from(sin), Real::from(cos));
+        (dx * cos - dy * sin, dx * sin + dy * cos)
     }
 
     pub async fn compute(
         self,
-        maxiter: usize,
+        options: ComputeOptions,
         ct: CancellationToken
     ) -> Option<MandelbrotSetWithHistogram> {
-        let Some((set, hist)) = compute_set_inner(self.left, self.bottom, self.scale, self.w, self.h, maxiter, ct.clone())
-        .await else {
-            return None;
-        };
-        Some(MandelbrotSetWithHistogram {
-            set,
-            hist,
-            maxiter
-        })
+        let (set, orbits, hist) = compute_set_inner(&self, options, ct, None).await?;
+        Some(MandelbrotSetWithHistogram::from_parts(set, orbits, hist, options.maxiter, self.w, self.h))
     }
 
-    pub fn zoom_to_selection(&self, selection: Rect) -> Self {
-        Self::new(
-            self.left + Real::from(selection.left()) * self.scale,
-            self.bottom + Real::from(selection.top()) * self.scale,
-            self.scale * Real::from(selection.width()) / (self.w as u32).into(),
-            self.w,
-            self.h
-        )
+    /// Same as `compute`, sending the progress to `progress` after
+    /// every row of pixels. The sender is dropped when the computation
+    /// ends, which tells receivers that no more progress is coming.
+    pub async fn compute_with_progress(
+        self,
+        options: ComputeOptions,
+        ct: CancellationToken,
+        progress: watch::Sender<Progress>
+    ) -> Option<MandelbrotSetWithHistogram> {
+        let (set, orbits, hist) = compute_set_inner(&self, options, ct, Some(&progress)).await?;
+        Some(MandelbrotSetWithHistogram::from_parts(set, orbits, hist, options.maxiter, self.w, self.h))
+    }
+
+    /// Zooms on the `w` by `h` pixels from `(x, y)`, with `y` growing
+    /// upwards, the width of the selection becoming that of the view.
+    pub fn zoom_to_selection(&self, x: i32, y: i32, w: u32, h: u32) -> Self {
+        let half = |size: u32| Real::from(size) / 2.0f32.into();
+        let (re, im) = self.pixel_to_complex(Real::from(x) + half(w), Real::from(y) + half(h));
+        Self {
+            re,
+            im,
+            radius: self.radius * Real::from(w) / (self.w as u32).into(),
+            ..self.clone()
+        }
     }
 
+    /// Resizes the view keeping its center and the size of pixels.
     pub fn fit_size(&self, w: usize, h: usize) -> Self {
-        Self::new(
-            self.left - Real::from(w as i32 - self.w as i32) * self.scale / 2.0f32.into(),
-            self.bottom - Real::from(h as i32- self.h as i32) * self.scale / 2.0f32.into(),
-            self.scale,
+        if h == 0 {
+            return self.clone();
+        }
+
+        Self {
+            radius: self.scale() * Real::from(h as u32) / 2.0f32.into(),
             w,
-            h
-        )
+            h,
+            ..self.clone()
+        }
+    }
+
+    /// Moves the view by a vector expressed in pixels, following
+    /// the current rotation.
+    pub fn pan(&self, dx: i32, dy: i32) -> Self {
+        let scale = self.scale();
+        let (dx, dy) = self.rotate_vector(Real::from(dx) * scale, Real::from(dy) * scale);
+        Self {
+            re: self.re + dx,
+            im: self.im + dy,
+            ..self.clone()
+        }
     }
+
+    /// Zooms in by `factor` keeping the center, values
+    /// below one zoom out.
+    pub fn zoom(&self, factor: f64) -> Self {
+        Self {
+            radius: self.radius / factor.into(),
+            ..self.clone()
+        }
+    }
+
+    /// The view at `t` on the way from `self`, at 0, to `other`, at 1.
+    /// The radius changes exponentially and the center moves at
+    /// a constant speed on screen, so that a zoom keeps its target
+    /// in view. Rotation takes the shortest way, the size is `self`'s.
+    pub fn interpolate(&self, other: &Self, t: f64) -> Self {
+        let ratio: f64 = (other.radius / self.radius).into();
+        let zoom = ratio.powf(t);
+        let moved = if (ratio - 1.0).abs() < 1e-9 {
+            t
+        } else {
+            (1.0 - zoom) / (1.0 - ratio)
+        };
+        let turn = (other.rotation - self.rotation + std::f64::consts::PI)
+            .rem_euclid(std::f64::consts::TAU) - std::f64::consts::PI;
+
+        Self {
+            re: self.re + (other.re - self.re) * moved.into(),
+            im: self.im + (other.im - self.im) * moved.into(),
+            radius: self.radius * zoom.into(),
+            ..self.clone()
+        }.rotate(turn * t)
+    }
+
+    /// Rotates the view counterclockwise by `angle` radians
+    /// around its center.
+    pub fn rotate(&self, angle: f64) -> Self {
+        Self {
+            rotation: (self.rotation + angle).rem_euclid(std::f64::consts::TAU),
+            ..self.clone()
+        }
+    }
+}
+
+/// Average of a quantity over the orbit, blended between the
+/// averages with and without the last term like the smooth iteration
+/// count, so that it is continuous across iteration counts.
+#[derive(Debug, Clone, Copy, Default)]
+struct Average {
+    sum: f64,
+    last: f64,
+    count: usize,
 }
 
-fn bounded<Real: Arithmetic>((a, b): (Real, Real), maxiter: usize) -> (bool, usize) {
+impl Average {
+    fn add(&mut self, term: f64) {
+        self.sum += term;
+        self.last = term;
+        self.count += 1;
+    }
+
+    /// `blend` is 1 right at the bailout radius, 0 one iteration further.
+    fn value(&self, blend: f64) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        let average = self.sum / self.count as f64;
+        let previous = if self.count > 1 {
+            (self.sum - self.last) / (self.count - 1) as f64
+        } else {
+            average
+        };
+        (previous + (average - previous) * blend) as f32
+    }
+}
+
+/// Iterates `c = (a, b)` until `|z|` reaches the bailout radius,
+/// `scale` being the size of a pixel on the complex plane.
+fn bounded<Real: Arithmetic>((a, b): (Real, Real), options: ComputeOptions, scale: f64) -> (Pixel, Orbit) {
+    let bailout = options.bailout.max(DEFAULT_BAILOUT);
+    let escape = Real::from((bailout * bailout) as f32);
+    let (ar, ai): (f64, f64) = (a.into(), b.into());
+    let c_modulus = ar.hypot(ai);
     let mut z: (Real, Real) = (0f32.into(), 0f32.into());
+    // Derivative of z with respect to c
+    let mut dz: (Real, Real) = (0f32.into(), 0f32.into());
     let mut i: usize = 0;
+    let mut stripe = Average::default();
+    let mut tia = Average::default();
 
-    while i < maxiter {
+    while i < options.maxiter {
+        let previous_modulus2: f64 = if options.tia {
+            (z.0 * z.0 + z.1 * z.1).into()
+        } else {
+            0.0
+        };
+
+        let dz0 = Real::from(2f32) * (z.0 * dz.0 - z.1 * dz.1) + 1f32.into();
+        dz.1 = Real::from(2f32) * (z.0 * dz.1 + z.1 * dz.0);
+        dz.0 = dz0;
         let z0 = (z.0 * z.0 - z.1 * z.1) + a;
         z.1 = Real::from(2f32) * z.0 * z.1 + b;
         z.0 = z0;
         i += 1;
 
-        if z.0 * z.0 + z.1 * z.1 >= Real::from(4f32) {
-            return (false, i);
+        if let Some(density) = options.stripe_density {
+            let (zr, zi): (f64, f64) = (z.0.into(), z.1.into());
+            stripe.add(0.5 + 0.5 * (density * zi.atan2(zr)).sin());
+        }
+        if options.tia {
+            // Where |z| lies between the bounds |z²| - |c| and
+            // |z²| + |c| of the triangle inequality
+            let (zr, zi): (f64, f64) = (z.0.into(), z.1.into());
+            let low = (previous_modulus2 - c_modulus).abs();
+            let high = previous_modulus2 + c_modulus;
+            if high > low {
+                tia.add((zr.hypot(zi) - low) / (high - low));
+            }
+        }
+
+        if z.0 * z.0 + z.1 * z.1 >= escape {
+            let (zr, zi): (f64, f64) = (z.0.into(), z.1.into());
+            let (dzr, dzi): (f64, f64) = (dz.0.into(), dz.1.into());
+            let modulus = zr.hypot(zi);
+            let log = modulus.ln();
+            let blend = (1.0 - (log / bailout.ln()).log2()).clamp(0.0, 1.0);
+            // z / dz has the direction of z times the conjugate of dz
+            let (nr, ni) = (zr * dzr + zi * dzi, zi * dzr - zr * dzi);
+            let length = nr.hypot(ni);
+            let pixel = Pixel {
+                interior: false,
+                iterations: i,
+                smooth: (i as f64 + 1.0 - log.log2()) as f32,
+                distance: (2.0 * modulus * log / dzr.hypot(dzi) / scale) as f32,
+            };
+            let orbit = Orbit {
+                z: if options.angle { (zr as f32, zi as f32) } else { (0.0, 0.0) },
+                stripe: stripe.value(blend),
+                tia: tia.value(blend),
+                normal: if options.normal && length > 0.0 {
+                    ((nr / length) as f32, (ni / length) as f32)
+                } else {
+                    (0.0, 0.0)
+                },
+            };
+            return (pixel, orbit);
         }
     }
 
-    (true, i)
+    let pixel = Pixel {
+        interior: true,
+        iterations: i,
+        ..Default::default()
+    };
+    (pixel, Orbit::default())
 }
 
 async fn compute_set_inner<Real: Arithmetic>(
-    x_left: Real,
-    y_bottom: Real,
-    scale: Real,
-    w: usize,
-    h: usize,
-    maxiter: usize,
-    ct: CancellationToken
-) -> Option<(Vec<(bool, usize)>, Vec<usize>)> {
-    let mut set = vec![(false, 0usize); w * h];
+    sector: &Sector<Real>,
+    options: ComputeOptions,
+    ct: CancellationToken,
+    progress: Option<&watch::Sender<Progress>>
+) -> Option<(Vec<Pixel>, Orbits, Vec<usize>)> {
+    let start = Instant::now();
+    let maxiter = options.maxiter;
+    let (w, h) = (sector.w, sector.h);
+    let scale: f64 = sector.scale().into();
+    // Normals are found on the complex plane, turned back to the image
+    let (sin, cos) = (-sector.rotation() as f32).sin_cos();
+    let mut set = vec![Pixel::default(); w * h];
+    let mut orbits = Orbits::new(options, w * h);
     let mut hist = vec![0usize; maxiter + 1];
 
-    let mut tasks = Vec::<JoinHandle<(bool, usize)>>::with_capacity(w * h);
+    let mut tasks = Vec::<JoinHandle<(Pixel, Orbit)>>::with_capacity(w * h);
 
     for y in 0..h {
         for x in 0..w {
@@ -132,47 +692,102 @@ async fn compute_set_inner<Real: Arithmetic>(
                 return None;
             }
 
-            let max = maxiter;
+            let c = sector.pixel_to_complex(Real::from(x as u32), Real::from(y as u32));
             tasks.push(tokio::spawn(async move {
-                bounded((Real::from(x as f32) * scale + x_left, y_bottom + Real::from(y as f32) * scale), max)
+                bounded(c, options, scale)
             }));
         }
     }
 
+    let mut iterations = 0;
     for (t, pixel_index) in tasks.iter_mut().zip(0..) {
-        let result = t.await.ok()?;
-        hist[result.1] += 1;
-        set[pixel_index] = result;
+        let (pixel, mut orbit) = t.await.ok()?;
+        if !pixel.interior {
+            hist[pixel.iterations] += 1;
+        }
+        iterations += pixel.iterations as u64;
+        let (x, y) = orbit.normal;
+        orbit.normal = (x * cos - y * sin, x * sin + y * cos);
+        set[pixel_index] = pixel;
+        orbits.set(pixel_index, orbit);
+
+        if let Some(progress) = progress.filter(|_| (pixel_index + 1) % w == 0) {
+            progress.send_replace(Progress {
+                pixels: pixel_index + 1,
+                total: w * h,
+                iterations,
+                elapsed: start.elapsed(),
+            });
+        }
     }
 
-    Some((set, hist))
+    Some((set, orbits, hist))
 }
 
 impl MandelbrotSetWithHistogram {
-    pub fn get_image_from_palette(
-        &self,
-        palette: &Vec<(u8, u8, u8)>
-    ) -> Vec<(u8, u8, u8)> {
-        let mut color_remap = vec![0usize; self.maxiter + 1];
-        let pixel_count = self.set.len();
-
-        let buf: Vec<(u8, u8, u8)> = self.set.iter().map(|(b, i)| {
-            let color_index = if *b {
-                pixel_count 
-            } else if color_remap[*i] == 0 {
-                let c = self.hist
-                    .iter()
-                    .take(*i)
-                    .sum();
-                color_remap[*i] = c;
-                c
-            } else {
-                color_remap[*i]
-            } * (palette.len() - 1) / pixel_count;
-
-            palette[palette.len() - color_index - 1]
-        }).collect();
-
-        buf
+    fn from_parts(
+        set: Vec<Pixel>,
+        orbits: Orbits,
+        hist: Vec<usize>,
+        maxiter: usize,
+        w: usize,
+        h: usize
+    ) -> Self {
+        let cumulative = hist
+            .iter()
+            .scan(0, |sum, &count| {
+                *sum += count;
+                Some(*sum)
+            })
+            .collect();
+        Self { set, orbits, hist, cumulative, maxiter, w, h }
+    }
+
+    /// The histogram of a `w` by `h` image without its pixels, to
+    /// prepare colorizers for an image computed in tiles.
+    pub fn summary(hist: Vec<usize>, maxiter: usize, w: usize, h: usize) -> Self {
+        Self::from_parts(Vec::new(), Orbits::default(), hist, maxiter, w, h)
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.w, self.h)
+    }
+
+    /// Pixels, bottom-up.
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.set
+    }
+
+    /// The orbit of the pixel at `index` in `pixels`.
+    pub fn orbit(&self, index: usize) -> Orbit {
+        self.orbits.get(index)
+    }
+
+    pub fn maxiter(&self) -> usize {
+        self.maxiter
+    }
+
+    /// Number of points escaping after each number of iterations,
+    /// interior points aren't counted.
+    pub fn histogram(&self) -> &[usize] {
+        &self.hist
+    }
+
+    /// Number of points escaping within each number of iterations,
+    /// the last one being the number of escaping points.
+    pub fn cumulative_histogram(&self) -> &[usize] {
+        &self.cumulative
+    }
+
+    /// Histogram equalization, see `colorizer::Histogram`.
+    pub fn get_image_from_gradient(&self, gradient: &Gradient) -> Vec<(u8, u8, u8)> {
+        colorizer::colorize(self, &mut Histogram::new(gradient.clone()))
+    }
+
+    /// Colors pixels by their smooth iteration count, see
+    /// `colorizer::Smooth`. Unlike histogram coloring a point gets the
+    /// same color in every view, which keeps animations from flickering.
+    pub fn get_image_cyclic(&self, gradient: &Gradient, period: f32) -> Vec<(u8, u8, u8)> {
+        colorizer::colorize(self, &mut Smooth::new(gradient.clone(), period))
     }
 }
@@ -0,0 +1,182 @@
+//! Zoom movies: views interpolated between keyframes, written as
+//! numbered PNG frames or as an uncompressed YUV4MPEG2 stream.
+
+use std::io::{ self, Write };
+use crate::gradient;
+use crate::mandelbrot::{ Arithmetic, Sector };
+
+/// The view shown at `frame`, counting from zero, of a movie of
+/// `frames` going through all of `keyframes`.
+///
+/// Every segment between two keyframes takes a share of the movie
+/// proportional to how much it zooms, counting a zoom by a factor
+/// of e or less as one, so that the zoom speed stays constant.
+pub fn frame_sector<Real: Arithmetic>(
+    keyframes: &[Sector<Real>],
+    frame: usize,
+    frames: usize
+) -> Sector<Real> {
+    let weights: Vec<f64> = keyframes
+        .windows(2)
+        .map(|pair| {
+            let ratio: f64 = (pair[1].radius() / pair[0].radius()).into();
+            ratio.ln().abs().max(1.0)
+        })
+        .collect();
+    let mut position = frame as f64 / (frames.max(2) - 1) as f64 * weights.iter().sum::<f64>();
+
+    for (pair, weight) in keyframes.windows(2).zip(&weights) {
+        if position <= *weight {
+            return pair[0].interpolate(&pair[1], position / weight);
+        }
+        position -= weight;
+    }
+
+    // Only reached with a single keyframe, or through rounding
+    keyframes[keyframes.len() - 1].clone()
+}
+
+/// A straight zoom into the center of a view, rendering an image,
+/// called keyframe, every time the radius halves and resampling frames
+/// from them instead of computing every frame.
+///
+/// Keyframes are rendered at twice the frame size, so that even the
+/// frames right before the next keyframe take at least one keyframe
+/// pixel per frame pixel; each frame pixel averages four samples,
+/// in linear light.
+pub struct FastZoom<Real: Arithmetic> {
+    /// The first keyframe, at twice the frame size.
+    start: Sector<Real>,
+    /// Ratio between the radius of the first and of the last frame.
+    zoom: f64,
+    frames: usize,
+}
+
+impl<Real: Arithmetic> FastZoom<Real> {
+    /// Zooms into the center of `end` from the radius of `start`,
+    /// all frames are as big as `end`.
+    pub fn new(start: &Sector<Real>, end: &Sector<Real>, frames: usize) -> Result<Self, String> {
+        let zoom: f64 = (start.radius() / end.radius()).into();
+        if zoom < 1.0 {
+            return Err("The fast mode can only zoom in".to_string());
+        }
+
+        let (w, h) = end.size();
+        Ok(Self {
+            start: end.with_size(2 * w, 2 * h).zoom(1.0 / zoom),
+            zoom,
+            frames,
+        })
+    }
+
+    /// Number of keyframes rendered for the whole movie.
+    pub fn keyframes(&self) -> usize {
+        self.zoom.log2().floor() as usize + 1
+    }
+
+    /// Keyframe used by `frame`, and ratio between the radius of
+    /// the frame and the one of the keyframe, between 0.5 and 1.
+    fn locate(&self, frame: usize) -> (usize, f64) {
+        let halvings = frame as f64 / (self.frames.max(2) - 1) as f64 * self.zoom.log2();
+        let keyframe = (halvings.floor() as usize).min(self.keyframes() - 1);
+        (keyframe, 0.5f64.powf(halvings - keyframe as f64))
+    }
+
+    pub fn keyframe_for(&self, frame: usize) -> usize {
+        self.locate(frame).0
+    }
+
+    pub fn keyframe_sector(&self, keyframe: usize) -> Sector<Real> {
+        self.start.zoom(2.0f64.powi(keyframe as i32))
+    }
+
+    /// The view shown at `frame`, for metadata.
+    pub fn frame_sector(&self, frame: usize) -> Sector<Real> {
+        let (keyframe, scale) = self.locate(frame);
+        let (w, h) = self.start.size();
+        self.keyframe_sector(keyframe)
+            .zoom(1.0 / scale)
+            .with_size(w / 2, h / 2)
+    }
+
+    /// Builds `frame` from the image of its keyframe, see `keyframe_for`.
+    /// Both are stored bottom-up.
+    pub fn resample(&self, keyframe: &[(u8, u8, u8)], frame: usize) -> Vec<(u8, u8, u8)> {
+        let (_, scale) = self.locate(frame);
+        let (kw, kh) = self.start.size();
+        let (w, h) = (kw / 2, kh / 2);
+        // Keyframe pixels per frame pixel
+        let step = 2.0 * scale;
+        let linear = gradient::srgb_to_linear_table();
+
+        let pixel = |x: usize, y: usize| {
+            let (r, g, b) = keyframe[y.min(kh - 1) * kw + x.min(kw - 1)];
+            [r, g, b].map(|c| linear[c as usize] as f64)
+        };
+        let bilinear = |x: f64, y: f64| {
+            let (x, y) = (x.clamp(0.0, (kw - 1) as f64), y.clamp(0.0, (kh - 1) as f64));
+            let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+            let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+            let (a, b, c, d) = (pixel(x0, y0), pixel(x0 + 1, y0), pixel(x0, y0 + 1), pixel(x0 + 1, y0 + 1));
+            [0, 1, 2].map(|i| {
+                (a[i] * (1.0 - fx) + b[i] * fx) * (1.0 - fy) + (c[i] * (1.0 - fx) + d[i] * fx) * fy
+            })
+        };
+
+        let mut image = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                // Frame and keyframe share their center
+                let kx = w as f64 + (x as f64 - w as f64 / 2.0) * step;
+                let ky = h as f64 + (y as f64 - h as f64 / 2.0) * step;
+                let mut sum = [0.0; 3];
+                for (dx, dy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                    let sample = bilinear(kx + dx * step / 4.0, ky + dy * step / 4.0);
+                    for i in 0..3 {
+                        sum[i] += sample[i] / 4.0;
+                    }
+                }
+                image.push(gradient::linear_to_rgb8(sum.map(|c| c as f32)));
+            }
+        }
+
+        image
+    }
+}
+
+/// Writes frames as a YUV4MPEG2 stream, 4:4:4 with BT.601
+/// studio range, which encoders such as `ffmpeg` read from a pipe.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    w: usize,
+    h: usize,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut writer: W, w: usize, h: usize, fps: usize) -> io::Result<Self> {
+        writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", w, h, fps)?;
+        Ok(Self { writer, w, h })
+    }
+
+    /// Writes an image stored bottom-up, as returned by
+    /// `MandelbrotSetWithHistogram`.
+    pub fn write_frame(&mut self, image: &[(u8, u8, u8)]) -> io::Result<()> {
+        let mut planes: [Vec<u8>; 3] =
+            std::array::from_fn(|_| Vec::with_capacity(self.w * self.h));
+
+        for row in image.chunks(self.w).take(self.h).rev() {
+            for &(r, g, b) in row {
+                let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+                planes[0].push((16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8);
+                planes[1].push((128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8);
+                planes[2].push((128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8);
+            }
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        for plane in planes {
+            self.writer.write_all(&plane)?;
+        }
+        self.writer.flush()
+    }
+}
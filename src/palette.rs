@@ -0,0 +1,544 @@
+//! Palette files, read into gradients and written from them.
+//!
+//! * `.gpl`: GIMP palettes, colors become evenly spaced stops
+//!   blended in RGB, see `flat`;
+//! * `.map`: Fractint maps, 256 lines of `r g b` with optional comments,
+//!   also blended in RGB;
+//! * `.ugr`: UltraFractal gradients, only the first one of the file
+//!   is read, positions go from 0 to 400 and wrap around;
+//! * `.ggr`: GIMP gradients, made of segments blended in RGB or HSV
+//!   along linear, curved, sine or spherical profiles;
+//! * `.json`: stops as written by `write_palette`:
+//!
+//! ```json
+//! {
+//!   "name": "fire",
+//!   "interpolation": "oklab",
+//!   "stops": [
+//!     { "position": 0, "color": "#000000" },
+//!     { "position": 1, "color": "#ffff00" }
+//!   ]
+//! }
+//! ```
+//!
+//! Files with another extension are recognized by their content.
+//! Formats that only hold flat colors or linear segments get the
+//! gradient sampled when it isn't interpolated in RGB, so that
+//! exported palettes look the same.
+
+use std::{ fs, path::Path };
+use serde_json::Value;
+use crate::gradient::{ self, Gradient, Interpolation };
+use crate::location::{ format_color, parse_color };
+
+/// Stops sampled from gradients that can't be written as they are.
+const SAMPLES: usize = 64;
+
+/// Evenly spaced flat colors of a palette file, blended in RGB as
+/// they were made to be rather than with the default interpolation.
+pub fn flat(colors: &[(u8, u8, u8)]) -> Gradient {
+    let mut gradient = Gradient::from_colors(colors);
+    gradient.interpolation = Interpolation::Rgb;
+    gradient
+}
+
+/// Reads a GIMP `.gpl` palette, as evenly spaced gradient stops.
+pub fn read_gimp_palette(path: &Path) -> Result<Gradient, String> {
+    gimp_palette::Palette::read_from_file(path)
+        .map(|p| flat(&p
+            .get_colors()
+            .iter()
+            .map(|c| (c.r, c.g, c.b))
+            .collect::<Vec<(u8, u8, u8)>>()
+        ))
+        .map_err(|_e| format!(
+            "Error loading palette from {}",
+            path.to_string_lossy()
+        ))
+}
+
+/// Reads a palette in any of the formats of the module documentation.
+pub fn read_palette(path: &Path) -> Result<Gradient, String> {
+    let extension = extension(path);
+    if extension == "gpl" {
+        return read_gimp_palette(path);
+    }
+
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Error reading {}: {}", path.to_string_lossy(), e))?;
+    let parse = match extension.as_str() {
+        "map" => parse_map,
+        "ugr" => parse_ugr,
+        "ggr" => parse_ggr,
+        "json" => parse_json,
+        _ if text.starts_with("GIMP Palette") => return read_gimp_palette(path),
+        _ if text.starts_with("GIMP Gradient") => parse_ggr,
+        _ if text.trim_start().starts_with('{') => parse_json,
+        _ if text.contains("gradient:") => parse_ugr,
+        _ => parse_map,
+    };
+
+    parse(&text).map_err(|e| format!(
+        "Error loading palette from {}: {}",
+        path.to_string_lossy(),
+        e
+    ))
+}
+
+/// Writes `gradient` in the format matching the extension of `path`.
+pub fn write_palette(path: &Path, gradient: &Gradient, name: &str) -> Result<(), String> {
+    let text = match extension(path).as_str() {
+        "gpl" => write_gpl(gradient, name),
+        "map" => write_map(gradient),
+        "ugr" => write_ugr(gradient, name),
+        "ggr" => write_ggr(gradient, name),
+        "json" => write_json(gradient, name),
+        _ => return Err(format!("Unsupported palette format: {}", path.to_string_lossy())),
+    };
+
+    fs::write(path, text)
+        .map_err(|e| format!("Error writing {}: {}", path.to_string_lossy(), e))
+}
+
+fn extension(path: &Path) -> String {
+    path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// The stops of `gradient`, or samples of it when it isn't
+/// interpolated in RGB.
+fn linear_stops(gradient: &Gradient) -> Vec<(f32, (u8, u8, u8))> {
+    if gradient.interpolation == Interpolation::Rgb {
+        return gradient.stops().to_vec();
+    }
+
+    (0..=SAMPLES)
+        .map(|i| {
+            let position = i as f32 / SAMPLES as f32;
+            (position, gradient.sample(position))
+        })
+        .collect()
+}
+
+fn channel(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn parse_map(text: &str) -> Result<Gradient, String> {
+    let colors = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut values = line.split_whitespace().map(str::parse::<u8>);
+            match (values.next(), values.next(), values.next()) {
+                (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Ok((r, g, b)),
+                _ => Err(format!("Invalid line \"{}\"", line)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if colors.is_empty() {
+        return Err("No colors".to_string());
+    }
+
+    Ok(flat(&colors))
+}
+
+fn write_map(gradient: &Gradient) -> String {
+    (0..256)
+        .map(|i| {
+            let (r, g, b) = gradient.sample(i as f32 / 255.0);
+            format!("{} {} {}\n", r, g, b)
+        })
+        .collect()
+}
+
+fn write_gpl(gradient: &Gradient, name: &str) -> String {
+    let colors = if gradient.is_even() && gradient.interpolation == Interpolation::Rgb {
+        gradient.colors()
+    } else {
+        (0..256).map(|i| gradient.sample(i as f32 / 255.0)).collect()
+    };
+
+    let mut text = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", name);
+    for (r, g, b) in colors {
+        text.push_str(&format!("{:3} {:3} {:3}\tUntitled\n", r, g, b));
+    }
+    text
+}
+
+fn parse_ugr(text: &str) -> Result<Gradient, String> {
+    let body = text
+        .split_once("gradient:")
+        .ok_or_else(|| "No gradient".to_string())?
+        .1;
+    // The gradient ends with its opacity section or with its entry
+    let body = body
+        .split(['}', ':'])
+        .next()
+        .unwrap_or_default();
+
+    let mut smooth = false;
+    let mut index = None;
+    let mut stops = Vec::new();
+    for token in body.split_whitespace() {
+        let Some((key, value)) = token.split_once('=') else {
+            continue;
+        };
+        let number = || value
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid {} \"{}\"", key, value));
+
+        match key {
+            "smooth" => smooth = value == "yes",
+            "index" => index = Some(number()?.rem_euclid(400)),
+            "color" => {
+                let index = index.take().ok_or_else(|| "Color without index".to_string())?;
+                // Colors are stored as 0xbbggrr
+                let color = number()?;
+                let rgb = (color as u8, (color >> 8) as u8, (color >> 16) as u8);
+                stops.push((index as f32 / 400.0, rgb));
+            },
+            _ => {},
+        }
+    }
+
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (Some(&first), Some(&last)) = (stops.first(), stops.last()) else {
+        return Err("No colors".to_string());
+    };
+
+    // Closes the gradient with the color where it wraps around,
+    // unless it already spans all indices
+    let span = 1.0 - last.0 + first.0;
+    let f = if span > 0.0 { (1.0 - last.0) / span } else { 0.0 };
+    let blend = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * f).round() as u8;
+    let wrap = (
+        blend(last.1.0, first.1.0),
+        blend(last.1.1, first.1.1),
+        blend(last.1.2, first.1.2),
+    );
+    if first.0 > 0.0 || last.0 < 399.0 / 400.0 {
+        stops.insert(0, (0.0, wrap));
+        stops.push((1.0, wrap));
+    }
+
+    Gradient::new(
+        &stops,
+        if smooth { Interpolation::Spline } else { Interpolation::Rgb }
+    )
+}
+
+fn write_ugr(gradient: &Gradient, name: &str) -> String {
+    let (stops, smooth) = match gradient.interpolation {
+        Interpolation::Spline => (gradient.stops().to_vec(), "yes"),
+        _ => (linear_stops(gradient), "no"),
+    };
+    let title: String = name
+        .chars()
+        .filter(|&c| c != '"')
+        .collect();
+    let entry: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let mut text = format!(
+        "{} {{\ngradient:\n  title=\"{}\" smooth={}\n",
+        if entry.is_empty() { "gradient" } else { &entry },
+        title,
+        smooth
+    );
+    let mut last_index = None;
+    for (position, (r, g, b)) in stops {
+        // Index 400 is index 0 again, the last index ends the gradient
+        let index = (position * 399.0).round() as i64;
+        if last_index == Some(index) {
+            continue;
+        }
+        last_index = Some(index);
+        let color = r as i64 | (g as i64) << 8 | (b as i64) << 16;
+        text.push_str(&format!("  index={} color={}\n", index, color));
+    }
+    text.push_str("opacity:\n  smooth=no index=0 opacity=255\n}\n");
+    text
+}
+
+fn parse_ggr(text: &str) -> Result<Gradient, String> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("GIMP Gradient") {
+        return Err("Not a GIMP gradient".to_string());
+    }
+
+    let mut count = None;
+    for line in lines.by_ref() {
+        if line.starts_with("Name:") || line.is_empty() {
+            continue;
+        }
+        count = line.parse::<usize>().ok();
+        break;
+    }
+    let count = count.ok_or_else(|| "Missing segment count".to_string())?;
+
+    let mut stops = Vec::new();
+    for line in lines.take(count) {
+        let values = line
+            .split_whitespace()
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("Invalid segment \"{}\"", line))?;
+        if values.len() < 12 {
+            return Err(format!("Invalid segment \"{}\"", line));
+        }
+
+        let (left, middle, right) = (values[0], values[1], values[2]);
+        let start = [values[3], values[4], values[5]];
+        let end = [values[7], values[8], values[9]];
+        let blending = values[11] as u8;
+        let coloring = values.get(12).copied().unwrap_or(0.0) as u8;
+        let width = right - left;
+        let middle = if width > 0.0 { (middle - left) / width } else { 0.5 };
+
+        let color_at = |f: f64| -> (u8, u8, u8) {
+            let factor = segment_factor(blending, middle, f);
+            let lerp = |a: f64, b: f64| a + (b - a) * factor;
+            let rgb = match coloring {
+                // Counterclockwise and clockwise hue
+                1 | 2 => {
+                    let hsv = |c: [f64; 3]| gradient::rgb_to_hsv(c.map(|v| v as f32)).map(|v| v as f64);
+                    let (a, mut b) = (hsv(start), hsv(end));
+                    if coloring == 1 && b[0] < a[0] {
+                        b[0] += 1.0;
+                    } else if coloring == 2 && b[0] > a[0] {
+                        b[0] -= 1.0;
+                    }
+                    let h = lerp(a[0], b[0]).rem_euclid(1.0);
+                    gradient::hsv_to_rgb([h, lerp(a[1], b[1]), lerp(a[2], b[2])].map(|v| v as f32))
+                        .map(|v| v as f64)
+                },
+                _ => [0, 1, 2].map(|i| lerp(start[i], end[i])),
+            };
+            (channel(rgb[0]), channel(rgb[1]), channel(rgb[2]))
+        };
+
+        // Linear RGB segments are exact with their middle, others are sampled
+        let samples: Vec<f64> = if blending == 0 && coloring == 0 {
+            vec![0.0, middle, 1.0]
+        } else {
+            (0..=8).map(|i| i as f64 / 8.0).collect()
+        };
+        for f in samples {
+            stops.push(((left + width * f) as f32, color_at(f)));
+        }
+    }
+
+    Gradient::new(&stops, Interpolation::Rgb)
+}
+
+/// Position between the colors of a GIMP gradient segment at `f`,
+/// for the blending function `blending` and the relative `middle`.
+fn segment_factor(blending: u8, middle: f64, f: f64) -> f64 {
+    let linear = || if f <= middle {
+        if middle > 0.0 { 0.5 * f / middle } else { 0.0 }
+    } else if middle < 1.0 {
+        0.5 + 0.5 * (f - middle) / (1.0 - middle)
+    } else {
+        1.0
+    };
+
+    match blending {
+        // Curved
+        1 => f.powf(0.5f64.ln() / middle.max(1e-10).ln()),
+        // Sine
+        2 => ((-std::f64::consts::FRAC_PI_2 + std::f64::consts::PI * linear()).sin() + 1.0) / 2.0,
+        // Sphere increasing and decreasing
+        3 => (1.0 - (linear() - 1.0).powi(2)).sqrt(),
+        4 => 1.0 - (1.0 - linear().powi(2)).sqrt(),
+        _ => linear(),
+    }
+}
+
+fn write_ggr(gradient: &Gradient, name: &str) -> String {
+    let stops = linear_stops(gradient);
+    let segments: Vec<_> = stops
+        .windows(2)
+        .filter(|pair| pair[1].0 > pair[0].0)
+        .collect();
+    let float = |(r, g, b): (u8, u8, u8)| format!(
+        "{:.6} {:.6} {:.6} 1.000000",
+        r as f64 / 255.0,
+        g as f64 / 255.0,
+        b as f64 / 255.0
+    );
+
+    let mut text = format!("GIMP Gradient\nName: {}\n", name);
+    if segments.is_empty() {
+        // A single color
+        let color = float(stops[0].1);
+        text.push_str(&format!("1\n0.000000 0.500000 1.000000 {} {} 0 0\n", color, color));
+        return text;
+    }
+
+    text.push_str(&format!("{}\n", segments.len()));
+    for (i, pair) in segments.iter().enumerate() {
+        let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+        // Stretches the ends so that segments cover the whole range
+        let left = if i == 0 { 0.0 } else { p0 as f64 };
+        let right = if i == segments.len() - 1 { 1.0 } else { p1 as f64 };
+        text.push_str(&format!(
+            "{:.6} {:.6} {:.6} {} {} 0 0\n",
+            left,
+            (p0 as f64 + p1 as f64) / 2.0,
+            right,
+            float(c0),
+            float(c1)
+        ));
+    }
+    text
+}
+
+fn parse_json(text: &str) -> Result<Gradient, String> {
+    let root: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let interpolation = match root.get("interpolation") {
+        Some(Value::String(name)) => Interpolation::from_name(name)?,
+        Some(_) => return Err("\"interpolation\" must be a string".to_string()),
+        // Written before gradients were blended in linear light
+        None => Interpolation::Rgb,
+    };
+    let Some(Value::Array(stops)) = root.get("stops") else {
+        return Err("Missing \"stops\" array".to_string());
+    };
+
+    let stops = stops
+        .iter()
+        .map(|stop| match (
+            stop.get("position").and_then(Value::as_f64),
+            stop.get("color").and_then(Value::as_str)
+        ) {
+            (Some(position), Some(color)) => Ok((position as f32, parse_color(color)?)),
+            _ => Err("Stops need a \"position\" number and a \"color\" string".to_string()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Gradient::new(&stops, interpolation)
+}
+
+fn write_json(gradient: &Gradient, name: &str) -> String {
+    let stops: Vec<String> = gradient
+        .stops()
+        .iter()
+        .map(|&(position, color)| format!(
+            "    {{ \"position\": {}, \"color\": \"{}\" }}",
+            position,
+            format_color(color)
+        ))
+        .collect();
+
+    format!(
+        "{{\n  \"name\": {},\n  \"interpolation\": \"{}\",\n  \"stops\": [\n{}\n  ]\n}}\n",
+        Value::from(name),
+        gradient.interpolation.name(),
+        stops.join(",\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_gradient(interpolation: Interpolation) -> Gradient {
+        Gradient::new(
+            &[(0.0, (0, 0, 64)), (0.25, (255, 255, 255)), (0.7, (200, 40, 0)), (1.0, (0, 0, 0))],
+            interpolation
+        ).unwrap()
+    }
+
+    /// Both gradients give the same colors, give or take `tolerance`.
+    fn assert_close(a: &Gradient, b: &Gradient, tolerance: u8) {
+        for i in 0..=1000 {
+            let t = i as f32 / 1000.0;
+            let ((r0, g0, b0), (r1, g1, b1)) = (a.sample(t), b.sample(t));
+            assert!(
+                r0.abs_diff(r1) <= tolerance && g0.abs_diff(g1) <= tolerance && b0.abs_diff(b1) <= tolerance,
+                "{:?} and {:?} differ at {}", a.sample(t), b.sample(t), t
+            );
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for interpolation in [Interpolation::Rgb, Interpolation::Oklab, Interpolation::Spline] {
+            let gradient = test_gradient(interpolation);
+            assert_eq!(parse_json(&write_json(&gradient, "a \"quoted\" name")), Ok(gradient));
+        }
+    }
+
+    #[test]
+    fn json_nesting_is_an_error() {
+        let deep = "[".repeat(200_000);
+        assert!(parse_json(&deep).is_err());
+        assert!(parse_json(&format!("{{ \"stops\": {} }}", deep)).is_err());
+    }
+
+    #[test]
+    fn map_round_trip() {
+        let gradient = test_gradient(Interpolation::Rgb);
+        let parsed = parse_map(&write_map(&gradient)).unwrap();
+
+        for i in 0..256 {
+            let t = i as f32 / 255.0;
+            assert_eq!(parsed.sample(t), gradient.sample(t));
+        }
+    }
+
+    #[test]
+    fn ugr_round_trip() {
+        let gradient = test_gradient(Interpolation::Rgb);
+        assert_close(&parse_ugr(&write_ugr(&gradient, "test")).unwrap(), &gradient, 2);
+
+        let smooth = parse_ugr(&write_ugr(&test_gradient(Interpolation::Spline), "test")).unwrap();
+        assert_eq!(smooth.interpolation, Interpolation::Spline);
+    }
+
+    #[test]
+    fn ggr_round_trip() {
+        let gradient = test_gradient(Interpolation::Rgb);
+        assert_close(&parse_ggr(&write_ggr(&gradient, "test")).unwrap(), &gradient, 1);
+    }
+
+    #[test]
+    fn sampled_round_trip() {
+        // Gradients these formats can't hold are sampled, which cuts
+        // the corners the gradient takes at its stops
+        let gradient = test_gradient(Interpolation::Oklab);
+        assert_close(&parse_ggr(&write_ggr(&gradient, "test")).unwrap(), &gradient, 4);
+        assert_close(&parse_ugr(&write_ugr(&gradient, "test")).unwrap(), &gradient, 4);
+    }
+
+    #[test]
+    fn gpl_round_trip() {
+        let path = std::env::temp_dir().join(format!("palette-test-{}.gpl", std::process::id()));
+        let even = flat(&[(0, 0, 0), (255, 128, 0), (255, 255, 255)]);
+        write_palette(&path, &even, "test").unwrap();
+        let read = read_palette(&path);
+        let sampled = test_gradient(Interpolation::Rgb);
+        write_palette(&path, &sampled, "test").unwrap();
+        let read_sampled = read_palette(&path);
+        // New gradients are blended in linear light, which the palette
+        // can only hold sampled
+        let linear = Gradient::from_colors(&[(40, 40, 80), (200, 120, 60), (250, 250, 250)]);
+        write_palette(&path, &linear, "test").unwrap();
+        let read_linear = read_palette(&path);
+        _ = fs::remove_file(&path);
+
+        // Flat palettes are blended in sRGB, as they always were
+        assert_eq!(read, Ok(even));
+        assert_eq!(read.unwrap().interpolation, Interpolation::Rgb);
+        assert_close(&read_sampled.unwrap(), &sampled, 1);
+        assert_eq!(linear.interpolation, Interpolation::LinearRgb);
+        assert_close(&read_linear.unwrap(), &linear, 1);
+    }
+}
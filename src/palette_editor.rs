@@ -0,0 +1,299 @@
+//! Palette editor drawn over the bottom of the window: the gradient
+//! with its stops below it, and hue, saturation and value sliders for
+//! the selected stop.
+//!
+//! Clicking the gradient adds a stop, stops are dragged along it and
+//! Delete removes the selected one. Left/Right slide all stops along
+//! the gradient, Up/Down spread them away from or towards the selected
+//! stop, as far as the ends allow.
+
+use sdl2::{
+    event::Event,
+    keyboard::Keycode,
+    mouse::MouseButton,
+    pixels::Color,
+    rect::{ Point, Rect },
+    render::{ BlendMode, Canvas },
+    video::Window,
+};
+use mandelbrot_rs::gradient::{ self, Gradient };
+
+const MARGIN: i32 = 12;
+const BAR_HEIGHT: u32 = 28;
+const MARKER_SIZE: (u32, u32) = (9, 12);
+const SLIDER_HEIGHT: u32 = 12;
+const SLIDER_SPACING: i32 = 6;
+const PANEL_HEIGHT: u32 = 2 * MARGIN as u32
+    + BAR_HEIGHT
+    + MARKER_SIZE.1
+    + 3 * (SLIDER_HEIGHT + SLIDER_SPACING as u32);
+/// Stops are picked up this many pixels away from their marker.
+const GRAB_DISTANCE: i32 = 6;
+/// Shift of Left/Right, and spread factor of Up/Down.
+const OFFSET_STEP: f32 = 1.0 / 64.0;
+const STRETCH_STEP: f32 = 1.1;
+
+/// What `PaletteEditor::event` did with an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// The event is for the rest of the application.
+    Ignored,
+    /// The editor needs to be drawn again.
+    Handled,
+    /// The stops changed, the gradient needs to be rebuilt.
+    Changed,
+}
+
+/// What the mouse is dragging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Drag {
+    Stop,
+    /// Hue, saturation or value slider.
+    Slider(usize),
+}
+
+pub struct PaletteEditor {
+    /// Stops in the order they were added, not necessarily sorted.
+    stops: Vec<(f32, (u8, u8, u8))>,
+    selected: usize,
+    /// Hue, saturation and value of the selected stop, kept apart
+    /// so that the hue isn't lost on grays.
+    hsv: [f32; 3],
+    drag: Option<Drag>,
+}
+
+/// Where the parts of the editor are in a `w` by `h` window.
+struct Layout {
+    panel: Rect,
+    bar: Rect,
+    markers: Rect,
+    sliders: [Rect; 3],
+}
+
+impl Layout {
+    fn new(w: u32, h: u32) -> Self {
+        let top = h as i32 - PANEL_HEIGHT as i32;
+        let width = w.saturating_sub(2 * MARGIN as u32).max(1);
+        let bar = Rect::new(MARGIN, top + MARGIN, width, BAR_HEIGHT);
+        let markers = Rect::new(MARGIN, bar.bottom(), width, MARKER_SIZE.1);
+        let slider = |i: i32| Rect::new(
+            MARGIN,
+            markers.bottom() + SLIDER_SPACING + i * (SLIDER_HEIGHT as i32 + SLIDER_SPACING),
+            width,
+            SLIDER_HEIGHT
+        );
+
+        Self {
+            panel: Rect::new(0, top, w, PANEL_HEIGHT),
+            bar,
+            markers,
+            sliders: [slider(0), slider(1), slider(2)],
+        }
+    }
+
+    /// Position along the bar of the column `x`, between 0 and 1.
+    fn position(&self, x: i32) -> f32 {
+        ((x - self.bar.x()) as f32 / self.bar.width() as f32).clamp(0.0, 1.0)
+    }
+
+    fn column(&self, position: f32) -> i32 {
+        self.bar.x() + (position * self.bar.width() as f32).round() as i32
+    }
+}
+
+impl PaletteEditor {
+    pub fn new(gradient: &Gradient) -> Self {
+        let mut editor = Self {
+            stops: gradient.stops().to_vec(),
+            selected: 0,
+            hsv: [0.0; 3],
+            drag: None,
+        };
+        editor.select(0);
+        editor
+    }
+
+    pub fn stops(&self) -> &[(f32, (u8, u8, u8))] {
+        &self.stops
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index;
+        self.hsv = gradient::rgb_to_hsv(to_unit(self.stops[index].1));
+    }
+
+    /// Index of the stop whose marker is closest to column `x`, if
+    /// close enough to be picked up.
+    fn stop_at(&self, layout: &Layout, x: i32) -> Option<usize> {
+        self.stops
+            .iter()
+            .enumerate()
+            .map(|(i, &(position, _))| (i, (layout.column(position) - x).abs()))
+            .filter(|&(_, distance)| distance <= GRAB_DISTANCE)
+            .min_by_key(|&(_, distance)| distance)
+            .map(|(i, _)| i)
+    }
+
+    /// Sets channel `channel` of the selected stop from column `x`
+    /// of its slider.
+    fn set_channel(&mut self, layout: &Layout, channel: usize, x: i32) {
+        self.hsv[channel] = layout.position(x);
+        self.stops[self.selected].1 = from_unit(gradient::hsv_to_rgb(self.hsv));
+    }
+
+    /// Handles an event of a `w` by `h` window. Mouse events outside
+    /// the editor, and keys it doesn't use, are ignored.
+    pub fn event(&mut self, event: &Event, gradient: &Gradient, w: u32, h: u32) -> Response {
+        let layout = Layout::new(w, h);
+
+        match *event {
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. }
+                if layout.panel.contains_point(Point::new(x, y)) => {
+                let point = Point::new(x, y);
+                if let Some(channel) = layout.sliders.iter().position(|s| s.contains_point(point)) {
+                    self.drag = Some(Drag::Slider(channel));
+                    self.set_channel(&layout, channel, x);
+                    return Response::Changed;
+                }
+
+                if layout.bar.contains_point(point) || layout.markers.contains_point(point) {
+                    if let Some(i) = self.stop_at(&layout, x) {
+                        self.select(i);
+                        self.drag = Some(Drag::Stop);
+                        return Response::Handled;
+                    }
+
+                    if layout.bar.contains_point(point) {
+                        let position = layout.position(x);
+                        self.stops.push((position, gradient.sample(position)));
+                        self.select(self.stops.len() - 1);
+                        self.drag = Some(Drag::Stop);
+                        return Response::Changed;
+                    }
+                }
+
+                Response::Handled
+            },
+            Event::MouseMotion { x, .. } => match self.drag {
+                Some(Drag::Stop) => {
+                    self.stops[self.selected].0 = layout.position(x);
+                    Response::Changed
+                },
+                Some(Drag::Slider(channel)) => {
+                    self.set_channel(&layout, channel, x);
+                    Response::Changed
+                },
+                None => Response::Ignored,
+            },
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } if self.drag.is_some() => {
+                self.drag = None;
+                Response::Handled
+            },
+            Event::MouseButtonDown { x, y, .. } | Event::MouseButtonUp { x, y, .. }
+                if layout.panel.contains_point(Point::new(x, y)) => Response::Handled,
+            Event::KeyDown { keycode: Some(Keycode::Delete | Keycode::Backspace), .. } => {
+                if self.stops.len() > 1 {
+                    self.stops.remove(self.selected);
+                    self.select(self.selected.min(self.stops.len() - 1));
+                }
+                Response::Changed
+            },
+            Event::KeyDown { keycode: Some(keycode @ (Keycode::Left | Keycode::Right)), .. } => {
+                let offset = if keycode == Keycode::Left { -OFFSET_STEP } else { OFFSET_STEP };
+                let (first, last) = self.stops
+                    .iter()
+                    .fold((1.0f32, 0.0f32), |(first, last), &(p, _)| (first.min(p), last.max(p)));
+                let offset = offset.clamp(-first, 1.0 - last);
+                for (position, _) in &mut self.stops {
+                    *position = (*position + offset).clamp(0.0, 1.0);
+                }
+                Response::Changed
+            },
+            Event::KeyDown { keycode: Some(keycode @ (Keycode::Up | Keycode::Down)), .. } => {
+                let pivot = self.stops[self.selected].0;
+                let factor = if keycode == Keycode::Up {
+                    // No further than the ends
+                    self.stops.iter().fold(STRETCH_STEP, |factor, &(p, _)| match p {
+                        p if p > pivot => factor.min((1.0 - pivot) / (p - pivot)),
+                        p if p < pivot => factor.min(pivot / (pivot - p)),
+                        _ => factor,
+                    })
+                } else {
+                    1.0 / STRETCH_STEP
+                };
+                for (position, _) in &mut self.stops {
+                    *position = (pivot + (*position - pivot) * factor).clamp(0.0, 1.0);
+                }
+                Response::Changed
+            },
+            _ => Response::Ignored,
+        }
+    }
+
+    /// Draws the editor over the bottom of `canvas`, `gradient`
+    /// being the one built from its stops.
+    pub fn render(&self, canvas: &mut Canvas<Window>, gradient: &Gradient) -> Result<(), String> {
+        let (w, h) = canvas.output_size()?;
+        let layout = Layout::new(w, h);
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        canvas.fill_rect(layout.panel)?;
+        canvas.set_blend_mode(BlendMode::None);
+
+        // The gradient, then each slider, a column at a time
+        let columns = |canvas: &mut Canvas<Window>, rect: Rect, color: &dyn Fn(f32) -> (u8, u8, u8)| {
+            for x in rect.left()..rect.right() {
+                let (r, g, b) = color(layout.position(x));
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                canvas.draw_line(Point::new(x, rect.top()), Point::new(x, rect.bottom() - 1))?;
+            }
+            Ok::<(), String>(())
+        };
+        columns(canvas, layout.bar, &|t| gradient.sample(t))?;
+
+        let [hue, saturation, value] = self.hsv;
+        let hsv = |hsv: [f32; 3]| from_unit(gradient::hsv_to_rgb(hsv));
+        columns(canvas, layout.sliders[0], &|t| hsv([t, 1.0, 1.0]))?;
+        columns(canvas, layout.sliders[1], &|t| hsv([hue, t, value]))?;
+        columns(canvas, layout.sliders[2], &|t| hsv([hue, saturation, t]))?;
+
+        for (slider, level) in layout.sliders.iter().zip(self.hsv) {
+            let x = layout.column(level);
+            canvas.set_draw_color(Color::RGB(255, 255, 255));
+            canvas.draw_rect(Rect::new(x - 2, slider.y() - 2, 5, slider.height() + 4))?;
+        }
+
+        for (i, &(position, (r, g, b))) in self.stops.iter().enumerate() {
+            let marker = Rect::new(
+                layout.column(position) - MARKER_SIZE.0 as i32 / 2,
+                layout.markers.y(),
+                MARKER_SIZE.0,
+                MARKER_SIZE.1
+            );
+            canvas.set_draw_color(Color::RGB(r, g, b));
+            canvas.fill_rect(marker)?;
+            canvas.set_draw_color(if i == self.selected {
+                Color::RGB(255, 255, 0)
+            } else {
+                Color::RGB(255, 255, 255)
+            });
+            canvas.draw_rect(marker)?;
+            canvas.draw_line(
+                Point::new(marker.center().x(), layout.bar.top()),
+                Point::new(marker.center().x(), marker.top())
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn to_unit((r, g, b): (u8, u8, u8)) -> [f32; 3] {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
+}
+
+fn from_unit(color: [f32; 3]) -> (u8, u8, u8) {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    (channel(color[0]), channel(color[1]), channel(color[2]))
+}
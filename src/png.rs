@@ -0,0 +1,397 @@
+//! A minimal PNG encoder for 8 bit RGB images, written one row at a time
+//! so that images don't need to fit in memory, an animated PNG encoder,
+//! and a reader for the `tEXt` chunks holding our metadata.
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::deflate::core::{
+    compress_to_output,
+    create_comp_flags_from_zip_params,
+    CompressorOxide,
+    TDEFLFlush,
+    TDEFLStatus,
+};
+use std::io::{ self, Read, Write };
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Compressed data is buffered up to this size before writing an `IDAT` chunk.
+const IDAT_SIZE: usize = 1 << 16;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+fn crc(chunk_type: &[u8], data: &[u8]) -> u32 {
+    !chunk_type
+        .iter()
+        .chain(data)
+        .fold(0xffff_ffffu32, |c, &b| CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(chunk_type)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc(chunk_type, data).to_be_bytes())
+}
+
+/// Writes a PNG image row by row, top to bottom.
+pub struct PngWriter<W: Write> {
+    writer: W,
+    w: usize,
+    rows_left: usize,
+    compressor: Box<CompressorOxide>,
+    idat: Vec<u8>,
+}
+
+impl<W: Write> PngWriter<W> {
+    /// Writes the header and the `tEXt` chunks; characters that can't be
+    /// represented in Latin-1 are replaced with `?`.
+    pub fn new(mut writer: W, w: usize, h: usize, text: &[(String, String)]) -> io::Result<Self> {
+        write_header(&mut writer, w, h, text)?;
+
+        Ok(Self {
+            writer,
+            w,
+            rows_left: h,
+            compressor: Box::new(CompressorOxide::new(
+                create_comp_flags_from_zip_params(6, 15, 0)
+            )),
+            idat: Vec::with_capacity(IDAT_SIZE),
+        })
+    }
+
+    pub fn write_row(&mut self, row: &[(u8, u8, u8)]) -> io::Result<()> {
+        if row.len() != self.w || self.rows_left == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Row doesn't fit the image"));
+        }
+
+        let mut data = Vec::with_capacity(1 + self.w * 3);
+        // Filter type: none
+        data.push(0);
+        data.extend(row.iter().flat_map(|&(r, g, b)| [r, g, b]));
+        self.rows_left -= 1;
+
+        let flush = if self.rows_left == 0 { TDEFLFlush::Finish } else { TDEFLFlush::None };
+        self.deflate(&data, flush)
+    }
+
+    /// Writes the last chunks, all rows must have been written.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.rows_left != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Missing image rows"));
+        }
+
+        write_chunk(&mut self.writer, b"IEND", &[])?;
+        Ok(self.writer)
+    }
+
+    fn deflate(&mut self, mut data: &[u8], flush: TDEFLFlush) -> io::Result<()> {
+        loop {
+            let idat = &mut self.idat;
+            let (status, consumed) = compress_to_output(
+                &mut self.compressor,
+                data,
+                flush,
+                |bytes| {
+                    idat.extend_from_slice(bytes);
+                    true
+                }
+            );
+
+            match status {
+                TDEFLStatus::Okay | TDEFLStatus::Done => {},
+                _ => return Err(io::Error::other("Compression failed")),
+            }
+
+            if self.idat.len() >= IDAT_SIZE || (flush == TDEFLFlush::Finish && !self.idat.is_empty()) {
+                write_chunk(&mut self.writer, b"IDAT", &self.idat)?;
+                self.idat.clear();
+            }
+
+            data = &data[consumed..];
+            if data.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn write_header<W: Write>(
+    writer: &mut W,
+    w: usize,
+    h: usize,
+    text: &[(String, String)]
+) -> io::Result<()> {
+    if w == 0 || h == 0 || w > i32::MAX as usize || h > i32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid PNG size"));
+    }
+
+    writer.write_all(&SIGNATURE)?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend((w as u32).to_be_bytes());
+    ihdr.extend((h as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, adaptive filtering, no interlace
+    ihdr.extend([8, 2, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &ihdr)?;
+
+    for (keyword, value) in text {
+        let mut data = latin1(keyword);
+        data.truncate(79);
+        data.push(0);
+        data.extend(latin1(value));
+        write_chunk(writer, b"tEXt", &data)?;
+    }
+
+    Ok(())
+}
+
+/// Writes an animated PNG, looping forever, one whole frame at a time.
+pub struct ApngWriter<W: Write> {
+    writer: W,
+    w: usize,
+    h: usize,
+    delay_ms: u16,
+    frames_left: usize,
+    /// Shared by `fcTL` and `fdAT` chunks.
+    sequence: u32,
+}
+
+impl<W: Write> ApngWriter<W> {
+    /// Writes the header of an animation of `frames`, each one
+    /// shown for `delay_ms` milliseconds, see `PngWriter::new`.
+    pub fn new(
+        mut writer: W,
+        w: usize,
+        h: usize,
+        frames: usize,
+        delay_ms: u16,
+        text: &[(String, String)]
+    ) -> io::Result<Self> {
+        if frames == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No frames"));
+        }
+
+        write_header(&mut writer, w, h, text)?;
+
+        let mut actl = Vec::with_capacity(8);
+        actl.extend((frames as u32).to_be_bytes());
+        // Loops forever
+        actl.extend(0u32.to_be_bytes());
+        write_chunk(&mut writer, b"acTL", &actl)?;
+
+        Ok(Self { writer, w, h, delay_ms, frames_left: frames, sequence: 0 })
+    }
+
+    /// Writes a frame stored bottom-up, as `MandelbrotSetWithHistogram`
+    /// returns them.
+    pub fn write_frame(&mut self, image: &[(u8, u8, u8)]) -> io::Result<()> {
+        if image.len() != self.w * self.h || self.frames_left == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Frame doesn't fit the animation"
+            ));
+        }
+
+        let mut fctl = Vec::with_capacity(26);
+        fctl.extend(self.sequence.to_be_bytes());
+        fctl.extend((self.w as u32).to_be_bytes());
+        fctl.extend((self.h as u32).to_be_bytes());
+        // Offset
+        fctl.extend([0; 8]);
+        fctl.extend(self.delay_ms.to_be_bytes());
+        fctl.extend(1000u16.to_be_bytes());
+        // No disposal, frames replace the previous ones
+        fctl.extend([0, 0]);
+        write_chunk(&mut self.writer, b"fcTL", &fctl)?;
+        self.sequence += 1;
+
+        let mut data = Vec::with_capacity((1 + self.w * 3) * self.h);
+        for row in image.chunks(self.w).rev() {
+            // Filter type: none
+            data.push(0);
+            data.extend(row.iter().flat_map(|&(r, g, b)| [r, g, b]));
+        }
+        let data = compress_to_vec_zlib(&data, 6);
+
+        // The first frame, following the first fcTL, is the image
+        // shown by decoders without animations
+        if self.sequence == 1 {
+            write_chunk(&mut self.writer, b"IDAT", &data)?;
+        } else {
+            let mut fdat = Vec::with_capacity(4 + data.len());
+            fdat.extend(self.sequence.to_be_bytes());
+            fdat.extend(data);
+            write_chunk(&mut self.writer, b"fdAT", &fdat)?;
+            self.sequence += 1;
+        }
+
+        self.frames_left -= 1;
+        Ok(())
+    }
+
+    /// Writes the last chunk, all frames must have been written.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.frames_left != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Missing animation frames"));
+        }
+
+        write_chunk(&mut self.writer, b"IEND", &[])?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes a whole image stored bottom-up, as `MandelbrotSetWithHistogram`
+/// returns them.
+pub fn write_png<W: Write>(
+    writer: W,
+    image: &[(u8, u8, u8)],
+    w: usize,
+    h: usize,
+    text: &[(String, String)]
+) -> io::Result<W> {
+    let mut png = PngWriter::new(writer, w, h, text)?;
+
+    for row in image.chunks(w).take(h).rev() {
+        png.write_row(row)?;
+    }
+
+    png.finish()
+}
+
+fn latin1(text: &str) -> Vec<u8> {
+    text
+        .chars()
+        .map(|c| u8::try_from(c).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Size and text metadata of a PNG image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngInfo {
+    pub w: usize,
+    pub h: usize,
+    pub text: Vec<(String, String)>,
+}
+
+/// Reads size and `tEXt` chunks, skipping image data.
+pub fn read_info<R: Read>(mut reader: R) -> Result<PngInfo, String> {
+    let mut signature = [0u8; 8];
+    reader
+        .read_exact(&mut signature)
+        .map_err(|e| e.to_string())?;
+
+    if signature != SIGNATURE {
+        return Err("Not a PNG file".to_string());
+    }
+
+    let mut info = PngInfo { w: 0, h: 0, text: Vec::new() };
+
+    loop {
+        let mut header = [0u8; 8];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| "Truncated PNG file".to_string())?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let chunk_type = &header[4..8];
+
+        match chunk_type {
+            b"IHDR" | b"tEXt" => {
+                let mut data = Vec::new();
+                reader
+                    .by_ref()
+                    .take(length + 4)
+                    .read_to_end(&mut data)
+                    .map_err(|e| e.to_string())?;
+                if data.len() as u64 != length + 4 {
+                    return Err("Truncated PNG file".to_string());
+                }
+                data.truncate(length as usize);
+
+                if chunk_type == b"IHDR" && data.len() >= 8 {
+                    info.w = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+                    info.h = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+                } else if let Some(separator) = data.iter().position(|&b| b == 0) {
+                    let text = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+                    info.text.push((text(&data[..separator]), text(&data[separator + 1..])));
+                }
+            },
+            b"IEND" => return Ok(info),
+            _ => {
+                io::copy(&mut reader.by_ref().take(length + 4), &mut io::sink())
+                    .map_err(|e| e.to_string())?;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type and data of each chunk, after checking their CRC.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(png[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let chunk_type: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = &rest[8..8 + length];
+            let stored = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(stored, crc(&chunk_type, data));
+            chunks.push((chunk_type, data.to_vec()));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn apng_chunks() {
+        let frame = vec![(255, 0, 0); 6];
+        let mut writer = ApngWriter::new(Vec::new(), 2, 3, 3, 40, &[]).unwrap();
+        for _ in 0..3 {
+            writer.write_frame(&frame).unwrap();
+        }
+        assert!(writer.write_frame(&frame).is_err());
+        let chunks = chunks(&writer.finish().unwrap());
+
+        let types: Vec<&[u8]> = chunks.iter().map(|(t, _)| t.as_slice()).collect();
+        assert_eq!(types, [
+            b"IHDR", b"acTL", b"fcTL", b"IDAT", b"fcTL", b"fdAT", b"fcTL", b"fdAT", b"IEND"
+        ].map(|t| t.as_slice()));
+
+        let (_, actl) = &chunks[1];
+        assert_eq!(actl[..4], 3u32.to_be_bytes());
+
+        // fcTL and fdAT chunks share one sequence from 0
+        let sequence: Vec<u32> = chunks
+            .iter()
+            .filter(|(t, _)| t == b"fcTL" || t == b"fdAT")
+            .map(|(_, data)| u32::from_be_bytes(data[..4].try_into().unwrap()))
+            .collect();
+        assert_eq!(sequence, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn apng_missing_frames() {
+        let mut writer = ApngWriter::new(Vec::new(), 2, 3, 2, 40, &[]).unwrap();
+        writer.write_frame(&[(0, 0, 0); 6]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Exports of images too large to compute at once.
+//!
+//! The view is split in square tiles that are computed one after
+//! the other. Histogram coloring needs the whole image, so the tiles
+//! are computed twice: the first pass only collects the histogram,
+//! the second one colors the tiles and streams them to the file a
+//! band of tiles at a time, so memory use only depends on the
+//! width of the image.
+
+use tokio_util::sync::CancellationToken;
+use crate::export::ImageWriter;
+use crate::colorizer::{ self, Colorizer };
+use crate::dither::Dither;
+use crate::mandelbrot::{ Arithmetic, ComputeOptions, MandelbrotSetWithHistogram, Sector };
+
+/// Default size of the side of a tile, in pixels.
+pub const TILE_SIZE: usize = 512;
+
+/// Computes `sector` tile by tile and writes it to `writer`, with
+/// `colorizer` prepared for the histogram of the whole image and
+/// colors rounded with `dither`.
+/// `progress` is called with the number of tiles done and the total,
+/// counting both passes.
+pub async fn export_poster<Real: Arithmetic>(
+    sector: &Sector<Real>,
+    options: ComputeOptions,
+    (colorizer, dither): (&mut dyn Colorizer, Dither),
+    tile_size: usize,
+    mut writer: ImageWriter,
+    ct: CancellationToken,
+    mut progress: impl FnMut(usize, usize)
+) -> Result<(), String> {
+    let (w, h) = sector.size();
+    let tile_size = tile_size.max(1);
+    let columns = w.div_ceil(tile_size);
+    let bands = h.div_ceil(tile_size);
+    let total = 2 * columns * bands;
+    let mut done = 0;
+
+    // Tiles of the band `band`, counted from the top, and their
+    // position with y growing upwards.
+    let band_tiles = |band: usize| {
+        let th = tile_size.min(h - band * tile_size);
+        let y = h - band * tile_size - th;
+        (0..columns).map(move |column| {
+            let x = column * tile_size;
+            let tw = tile_size.min(w - x);
+            (x, y, tw, th)
+        })
+    };
+    let compute = |(x, y, tw, th): (usize, usize, usize, usize)| sector
+        .tile(x, y, tw, th)
+        .compute(options, ct.clone());
+
+    let mut hist = vec![0usize; options.maxiter + 1];
+    for band in 0..bands {
+        for tile in band_tiles(band) {
+            let set = compute(tile)
+                .await
+                .ok_or_else(|| "Export cancelled".to_string())?;
+            for (sum, count) in hist.iter_mut().zip(set.histogram()) {
+                *sum += count;
+            }
+            done += 1;
+            progress(done, total);
+        }
+    }
+
+    colorizer.prepare(&MandelbrotSetWithHistogram::summary(hist, options.maxiter, w, h));
+
+    for band in 0..bands {
+        let mut rows: Vec<Vec<(u8, u8, u8)>> = Vec::new();
+        for (x, y, tw, th) in band_tiles(band) {
+            let set = compute((x, y, tw, th))
+                .await
+                .ok_or_else(|| "Export cancelled".to_string())?;
+            let image = colorizer::colorize_prepared(&set, colorizer, dither, (x, y));
+            rows.resize_with(th, || Vec::with_capacity(w));
+            // Tiles are bottom-up, rows are written top-down
+            for (row, tile_row) in rows.iter_mut().zip(image.chunks(tw).rev()) {
+                row.extend_from_slice(tile_row);
+            }
+            done += 1;
+            progress(done, total);
+        }
+
+        for row in rows {
+            writer.write_row(&row)?;
+        }
+    }
+
+    writer.finish()
+}
@@ -0,0 +1,335 @@
+//! Exports of the raw per-pixel data, for post-processing in other
+//! tools. Every format holds four layers, see `Pixel`:
+//!
+//! * `iterations`: iterations before escaping, the maximum for
+//!   interior points;
+//! * `smooth`: continuous iteration count;
+//! * `distance`: estimated distance to the set, in pixels;
+//! * `interior`: 1 for points that didn't escape, 0 otherwise.
+//!
+//! Rows are stored top-down, and layouts differ by format:
+//!
+//! * `.npy`: little-endian `float32` array of shape `(height, width, 4)`,
+//!   the last axis holding the layers in the order above;
+//! * `.exr`: uncompressed scanline OpenEXR, one `FLOAT` channel
+//!   per layer, named as above;
+//! * `.tif`/`.tiff`: uncompressed 16 bits unsigned, 4 samples per pixel
+//!   in the order above, with `iterations` clamped to 65535, `smooth`
+//!   scaled so that the maximum number of iterations is 65535,
+//!   `distance` in 1/256 of pixel clamped to 65535, and `interior`
+//!   either 0 or 65535. The scaling is repeated in the image description.
+//!   Samples are tagged as RGB plus an extra sample, as most readers
+//!   don't accept more than one sample for grayscale images.
+
+use std::{
+    fs::File,
+    io::{ self, BufWriter, Write },
+    path::Path,
+};
+use crate::mandelbrot::{ MandelbrotSetWithHistogram, Pixel };
+
+/// Writes all layers of `set` in the format matching the extension of `path`.
+pub fn write_raw(path: &Path, set: &MandelbrotSetWithHistogram) -> Result<(), String> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let write: fn(&mut BufWriter<File>, &MandelbrotSetWithHistogram) -> io::Result<()> =
+        match extension.as_str() {
+            "npy" => write_npy,
+            "exr" => write_exr,
+            "tif" | "tiff" => write_tiff,
+            _ => return Err(format!("Unsupported data format: {}", path.to_string_lossy())),
+        };
+
+    if set.pixels().is_empty() {
+        return Err("Nothing to export".to_string());
+    }
+
+    let file = File::create(path)
+        .map_err(|e| format!("Error creating {}: {}", path.to_string_lossy(), e))?;
+    let mut writer = BufWriter::new(file);
+
+    write(&mut writer, set)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("Error writing {}: {}", path.to_string_lossy(), e))
+}
+
+/// Layers of a pixel, in the order of the module documentation.
+fn layers(pixel: &Pixel) -> [f32; 4] {
+    [
+        pixel.iterations as f32,
+        pixel.smooth,
+        pixel.distance,
+        if pixel.interior { 1.0 } else { 0.0 },
+    ]
+}
+
+/// Rows of the set, top-down.
+fn rows(set: &MandelbrotSetWithHistogram) -> impl Iterator<Item = &[Pixel]> {
+    let (w, h) = set.size();
+    set.pixels().chunks(w).take(h).rev()
+}
+
+pub fn write_npy<W: Write>(writer: &mut W, set: &MandelbrotSetWithHistogram) -> io::Result<()> {
+    let (w, h) = set.size();
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, 4), }}",
+        h, w
+    );
+    // Magic, version and header length take 10 bytes, the whole
+    // header is padded to a multiple of 64 and ends with a newline.
+    let padding = 63 - (10 + header.len()) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+
+    for row in rows(set) {
+        let bytes: Vec<u8> = row
+            .iter()
+            .flat_map(layers)
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        writer.write_all(&bytes)?;
+    }
+
+    Ok(())
+}
+
+pub fn write_exr<W: Write>(writer: &mut W, set: &MandelbrotSetWithHistogram) -> io::Result<()> {
+    let (w, h) = set.size();
+    // Channels must be sorted by name
+    let channels = [("distance", 2), ("interior", 3), ("iterations", 0), ("smooth", 1)];
+
+    let mut header = Vec::new();
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        header.extend(name.as_bytes());
+        header.push(0);
+        header.extend(kind.as_bytes());
+        header.push(0);
+        header.extend((value.len() as i32).to_le_bytes());
+        header.extend(value);
+    };
+
+    let mut chlist = Vec::new();
+    for (name, _) in channels {
+        chlist.extend(name.as_bytes());
+        chlist.push(0);
+        // FLOAT pixels, not linear, reserved, x and y sampling
+        chlist.extend(2i32.to_le_bytes());
+        chlist.extend([0, 0, 0, 0]);
+        chlist.extend(1i32.to_le_bytes());
+        chlist.extend(1i32.to_le_bytes());
+    }
+    chlist.push(0);
+
+    let window: Vec<u8> = [0, 0, w as i32 - 1, h as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+
+    attribute("channels", "chlist", &chlist);
+    attribute("compression", "compression", &[0]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    // Magic number and version 2, single part scanline file
+    writer.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+    writer.write_all(&header)?;
+
+    // One line per block, each preceded by its y and size
+    let block_size = 8 + w * channels.len() * 4;
+    let first_block = 8 + header.len() + h * 8;
+    for y in 0..h {
+        writer.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+    }
+
+    for (y, row) in rows(set).enumerate() {
+        let mut block = Vec::with_capacity(block_size);
+        block.extend((y as i32).to_le_bytes());
+        block.extend(((block_size - 8) as i32).to_le_bytes());
+        for (_, layer) in channels {
+            block.extend(row
+                .iter()
+                .flat_map(|pixel| layers(pixel)[layer].to_le_bytes())
+            );
+        }
+        writer.write_all(&block)?;
+    }
+
+    Ok(())
+}
+
+pub fn write_tiff<W: Write>(writer: &mut W, set: &MandelbrotSetWithHistogram) -> io::Result<()> {
+    let (w, h) = set.size();
+    let maxiter = set.maxiter().max(1) as f32;
+    let mut description = format!(
+        "Layers: iterations, smooth * 65535 / {}, distance * 256, interior * 65535\0",
+        maxiter
+    );
+    // Keeps the pixels on a word boundary
+    if description.len() % 2 == 1 {
+        description.push('\0');
+    }
+    let data_size = w * h * 4 * 2;
+
+    if data_size > u32::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image too large for TIFF"));
+    }
+
+    // Header, directory with its entries, then the values not fitting
+    // in an entry and finally the pixels.
+    const ENTRIES: usize = 12;
+    let extra_offset = 8 + 2 + ENTRIES * 12 + 4;
+    let bits_offset = extra_offset;
+    let description_offset = bits_offset + 8;
+    let data_offset = description_offset + description.len();
+
+    let mut tiff = Vec::with_capacity(data_offset);
+    tiff.extend(b"II");
+    tiff.extend(42u16.to_le_bytes());
+    tiff.extend(8u32.to_le_bytes());
+    tiff.extend((ENTRIES as u16).to_le_bytes());
+
+    // Short values fit in the first half of the value field
+    let mut entry = |tag: u16, kind: u16, count: usize, value: u32| {
+        tiff.extend(tag.to_le_bytes());
+        tiff.extend(kind.to_le_bytes());
+        tiff.extend((count as u32).to_le_bytes());
+        tiff.extend(value.to_le_bytes());
+    };
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    const ASCII: u16 = 2;
+    entry(256, LONG, 1, w as u32);
+    entry(257, LONG, 1, h as u32);
+    entry(258, SHORT, 4, bits_offset as u32);
+    // No compression
+    entry(259, SHORT, 1, 1);
+    // RGB
+    entry(262, SHORT, 1, 2);
+    entry(270, ASCII, description.len(), description_offset as u32);
+    entry(273, LONG, 1, data_offset as u32);
+    entry(277, SHORT, 1, 4);
+    entry(278, LONG, 1, h as u32);
+    entry(279, LONG, 1, data_size as u32);
+    // Chunky, samples of a pixel are contiguous
+    entry(284, SHORT, 1, 1);
+    // An extra sample of unspecified meaning
+    entry(338, SHORT, 1, 0);
+    tiff.extend(0u32.to_le_bytes());
+
+    tiff.extend([16u16; 4].iter().flat_map(|b| b.to_le_bytes()));
+    tiff.extend(description.as_bytes());
+    writer.write_all(&tiff)?;
+
+    let scale = [1.0, 65535.0 / maxiter, 256.0, 65535.0];
+    for row in rows(set) {
+        let bytes: Vec<u8> = row
+            .iter()
+            .flat_map(|pixel| {
+                let layers = layers(pixel);
+                // Float to integer casts saturate
+                [0, 1, 2, 3].map(|i| (layers[i] * scale[i]) as u16)
+            })
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        writer.write_all(&bytes)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_util::sync::CancellationToken;
+    use crate::mandelbrot::{ ComputeOptions, Sector };
+    use super::*;
+
+    /// A 2 by 3 pixels set, with interior and escaping points.
+    fn set() -> MandelbrotSetWithHistogram {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(Sector::<f64>::new((-0.5, 0.0), 1.5, 2, 3)
+                .compute(ComputeOptions::new(100), CancellationToken::new())
+            )
+            .unwrap()
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> usize {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as usize
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn npy_header() {
+        let mut npy = Vec::new();
+        write_npy(&mut npy, &set()).unwrap();
+
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        let data_offset = 10 + u16_at(&npy, 8);
+        assert_eq!(data_offset % 64, 0);
+        let header = std::str::from_utf8(&npy[10..data_offset]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (3, 2, 4), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(npy.len() - data_offset, 3 * 2 * 4 * 4);
+    }
+
+    #[test]
+    fn tiff_directory() {
+        let set = set();
+        let mut tiff = Vec::new();
+        write_tiff(&mut tiff, &set).unwrap();
+
+        assert_eq!(&tiff[..4], b"II\x2a\x00");
+        let directory = u32_at(&tiff, 4);
+        let entries: Vec<(usize, usize, usize)> = (0..u16_at(&tiff, directory))
+            .map(|i| directory + 2 + i * 12)
+            .map(|entry| (u16_at(&tiff, entry), u32_at(&tiff, entry + 4), u32_at(&tiff, entry + 8)))
+            .collect();
+        let value = |tag: usize| entries.iter().find(|entry| entry.0 == tag).unwrap().2;
+        // The next directory offset ends the directory
+        assert_eq!(u32_at(&tiff, directory + 2 + entries.len() * 12), 0);
+
+        assert_eq!((value(256), value(257), value(278)), (2, 3, 3));
+        let bits = value(258);
+        assert_eq!((0..4).map(|i| u16_at(&tiff, bits + 2 * i)).collect::<Vec<_>>(), vec![16; 4]);
+        let (_, count, description) = *entries.iter().find(|entry| entry.0 == 270).unwrap();
+        assert!(tiff[description..description + count].starts_with(b"Layers: iterations"));
+
+        let (data, size) = (value(273), value(279));
+        assert_eq!(size, 2 * 3 * 4 * 2);
+        assert_eq!(data % 2, 0);
+        assert_eq!(tiff.len(), data + size);
+
+        // The first sample is the iteration count of the top left pixel
+        let (w, h) = set.size();
+        assert_eq!(u16_at(&tiff, data), set.pixels()[w * (h - 1)].iterations);
+    }
+
+    #[test]
+    fn exr_offsets() {
+        let mut exr = Vec::new();
+        write_exr(&mut exr, &set()).unwrap();
+
+        let block_size = 8 + 2 * 4 * 4;
+        let first = u32_at(&exr, exr.len() - 3 * block_size - 3 * 8);
+        assert_eq!(first, exr.len() - 3 * block_size);
+        for y in 0..3 {
+            let offset = u32_at(&exr, first - 3 * 8 + y * 8);
+            assert_eq!(offset, first + y * block_size);
+            assert_eq!((u32_at(&exr, offset), u32_at(&exr, offset + 4)), (y, block_size - 8));
+        }
+    }
+}
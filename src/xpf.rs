@@ -0,0 +1,229 @@
+//! XaoS `.xpf` position files.
+//!
+//! These are scripts made of commands like `(view -0.75 0 2.5 2.5)`,
+//! where the last two numbers are the width and height of the view,
+//! which is fitted in the image as XaoS does. The center keeps all
+//! the digits of the file, see `Location::coordinates`.
+//! We map `view`, `angle` and `maxiter` and report any other command
+//! that would change the picture as a warning. Palettes can't be
+//! expressed in XaoS terms, so they are neither read nor written
+//! and imported locations are black and white.
+
+use crate::colorizer::PaletteMap;
+use crate::gradient::Gradient;
+use crate::location::{ Location, PaletteSource };
+use crate::mandelbrot::{ Arithmetic, Coloring, Formula, Sector };
+
+/// Commands, with their arguments, that don't change what we draw.
+const HARMLESS_COMMANDS: &[&str] = &[
+    "initstate", "formula mandel", "julia #f", "plane 0",
+    "outcoloring 0", "incoloring 0", "bailout 4", "periodicity #t",
+    "periodicity #f", "fastrotate #t", "fastrotate #f",
+];
+
+pub fn parse<Real: Arithmetic>(
+    text: &str,
+    w: usize,
+    h: usize
+) -> Result<(Location<Real>, Vec<String>), String> {
+    let mut warnings = Vec::new();
+    let mut view = None;
+    let mut angle = 0.0;
+    let mut maxiter = 170;
+
+    for command in commands(text)? {
+        let number = |i: usize| -> Result<Real, String> {
+            let value = command
+                .get(i)
+                .ok_or_else(|| format!("Missing argument in ({})", command.join(" ")))?;
+            value
+                .parse::<Real>()
+                .map_err(|_| format!("Invalid number \"{}\" in ({})", value, command.join(" ")))
+        };
+
+        match command[0].as_str() {
+            "view" => view = Some((
+                (number(1)?, number(2)?),
+                (command[1].clone(), command[2].clone()),
+                fit(number(3)?, number(4)?, w, h)
+            )),
+            "angle" => angle = command
+                .get(1)
+                .and_then(|a| a.parse::<f64>().ok())
+                .ok_or_else(|| "Invalid angle".to_string())?,
+            "maxiter" => maxiter = command
+                .get(1)
+                .and_then(|i| i.parse::<usize>().ok())
+                .ok_or_else(|| "Invalid maxiter".to_string())?,
+            _ if HARMLESS_COMMANDS.contains(&command.join(" ").as_str()) => {},
+            _ => warnings.push(format!("Ignoring ({})", command.join(" "))),
+        }
+    }
+
+    let (center, (re, im), radius) = view.ok_or_else(|| "Missing (view ...)".to_string())?;
+    if radius.partial_cmp(&0.0f32.into()) != Some(std::cmp::Ordering::Greater) {
+        return Err("View size must be greater than zero".to_string());
+    }
+
+    let sector = Sector::new(center, radius, w, h).rotate(angle.to_radians());
+    let (_, _, radius) = sector.center_radius();
+
+    Ok((
+        Location {
+            sector,
+            coordinates: Some((re, im, radius)),
+            formula: Formula::Mandelbrot,
+            maxiter,
+            bailout: None,
+            stripe_density: None,
+            coloring: Coloring::Histogram,
+            layers: Vec::new(),
+            shading: None,
+            palette: PaletteSource::Embedded(Gradient::default()),
+            palette_map: PaletteMap::default(),
+            palette_name: "black and white".to_string(),
+        },
+        warnings
+    ))
+}
+
+pub fn write<Real: Arithmetic>(location: &Location<Real>) -> String {
+    let (re, im, _) = location.center_radius();
+    let (w, h) = location.sector.size();
+    let height = location.sector.radius() * 2.0f32.into();
+    let width = height * Real::from(w as u32) / Real::from(h.max(1) as u32);
+
+    format!(
+        ";Position file written by Mandelbrot Explorer\n\
+        (initstate)\n\
+        (formula 'mandel)\n\
+        (view {} {} {} {})\n\
+        (angle {})\n\
+        (maxiter {})\n",
+        re,
+        im,
+        width,
+        height,
+        location.sector.rotation().to_degrees(),
+        location.maxiter
+    )
+}
+
+/// Radius of the smallest view of a `w` by `h` pixels image showing
+/// a `width` by `height` rectangle.
+fn fit<Real: Arithmetic>(width: Real, height: Real, w: usize, h: usize) -> Real {
+    let half = height / 2.0f32.into();
+    if w == 0 {
+        return half;
+    }
+
+    let half_width = width / 2.0f32.into() * Real::from(h as u32) / Real::from(w as u32);
+    if half_width > half { half_width } else { half }
+}
+
+/// Splits a script into commands, each one being a list of atoms
+/// with quotes removed from symbols and strings.
+fn commands(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut commands = Vec::new();
+    let mut command: Option<Vec<String>> = None;
+    let mut atom = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, command.as_mut()) {
+            (';', _) => {
+                chars.by_ref().find(|&c| c == '\n');
+            },
+            ('(', None) => command = Some(Vec::new()),
+            ('(', Some(_)) => return Err("Nested lists are not supported".to_string()),
+            (')', Some(current)) => {
+                if !atom.is_empty() {
+                    current.push(std::mem::take(&mut atom));
+                }
+                if !current.is_empty() {
+                    commands.push(std::mem::take(current));
+                }
+                command = None;
+            },
+            (')', None) => return Err("Unbalanced parenthesis".to_string()),
+            ('"', Some(current)) => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.extend(chars.next()),
+                        Some(c) => string.push(c),
+                        None => return Err("Unterminated string".to_string()),
+                    }
+                }
+                current.push(string);
+            },
+            (c, Some(current)) if c.is_whitespace() => {
+                if !atom.is_empty() {
+                    current.push(std::mem::take(&mut atom));
+                }
+            },
+            ('\'', Some(_)) if atom.is_empty() => {},
+            (c, Some(_)) => atom.push(c),
+            (c, None) if c.is_whitespace() => {},
+            (c, None) => return Err(format!("Unexpected '{}' outside of a command", c)),
+        }
+    }
+
+    if command.is_some() {
+        return Err("Unbalanced parenthesis".to_string());
+    }
+
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XPF: &str = "\
+;Position file written by XaoS
+(initstate)
+(formula 'mandel)
+(view -0.743643887037158704752191506114774 0.131825904205311970493132056385139 4e-20 3e-20)
+(angle 30)
+(maxiter 5000)
+";
+
+    #[test]
+    fn round_trip() {
+        let (location, warnings) = parse::<f64>(XPF, 640, 480).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(location.maxiter, 5000);
+        assert_eq!(location.sector.radius(), 1.5e-20);
+
+        // The center keeps the digits f64 can't hold
+        let text = write(&location);
+        assert!(text.contains(
+            "(view -0.743643887037158704752191506114774 0.131825904205311970493132056385139 "
+        ));
+
+        let (parsed, warnings) = parse::<f64>(&text, 640, 480).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(parsed.center_radius(), location.center_radius());
+        assert_eq!(parsed.maxiter, location.maxiter);
+        assert!((parsed.sector.rotation() - location.sector.rotation()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn wide_views_fit_the_image() {
+        let (location, _) = parse::<f64>("(view 0 0 8 2)", 640, 480).unwrap();
+        assert_eq!(location.sector.radius(), 3.0);
+    }
+
+    #[test]
+    fn malformed() {
+        let (_, warnings) = parse::<f64>("(view 0 0 4 3) (outcoloring 5)", 640, 480).unwrap();
+        assert_eq!(warnings, vec!["Ignoring (outcoloring 5)".to_string()]);
+
+        assert_eq!(parse::<f64>("(maxiter 100)", 640, 480), Err("Missing (view ...)".to_string()));
+        assert!(parse::<f64>("(view 0 0 4)", 640, 480).is_err());
+        assert!(parse::<f64>("(view 0 0 4 3", 640, 480).is_err());
+        assert!(parse::<f64>("(view 0 0 0 0)", 640, 480).is_err());
+    }
+}